@@ -0,0 +1,203 @@
+//! Typed interface to container managers, each manager owns its own flag differences so commands
+//! do not have to care which one is used
+
+mod cli;
+mod http;
+mod api;
+mod archive;
+mod attach;
+mod podman;
+mod podman_api;
+mod docker;
+mod docker_api;
+mod info;
+
+#[cfg(test)]
+mod stand_in;
+
+// only used through LM_MOCK_BACKEND in debug builds, tests use it directly
+#[cfg(any(debug_assertions, test))]
+pub mod mock;
+
+pub use podman::Podman;
+pub use podman_api::PodmanApi;
+pub use docker::Docker;
+pub use docker_api::DockerApi;
+pub use info::ContainerInfo;
+
+use crate::manager::ContainerManager;
+use crate::Result;
+use cli::CommandRunner;
+use std::io::BufRead;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Options used to create a container, manager specific flags are added by the backend
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+    pub name: String,
+    pub image: String,
+    pub hostname: String,
+
+    /// Use init system inside the container
+    pub init: bool,
+
+    /// Container manager is ran as root
+    pub root: bool,
+
+    /// Signal used to stop the container
+    pub stop_signal: Option<String>,
+
+    /// Environment variables in 'KEY=value' format
+    pub env: Vec<String>,
+
+    /// Labels as key value pairs
+    pub labels: Vec<(String, String)>,
+
+    /// Volumes in 'source:destination:options' format
+    pub volumes: Vec<String>,
+
+    /// Mounts in '--mount' format (eg. 'type=tmpfs,destination=/tmp')
+    pub mounts: Vec<String>,
+
+    /// Executable and arguments ran when the container starts
+    pub entrypoint: Vec<String>,
+
+    /// Extra arguments passed verbatim to the container manager
+    pub extra_args: Vec<String>,
+}
+
+/// Options used to execute a command inside a running container
+#[derive(Debug, Clone, Default)]
+pub struct ExecOptions {
+    pub container_name: String,
+    pub user: Option<String>,
+    pub workdir: Option<String>,
+
+    /// Environment variables in 'KEY=value' format
+    pub env: Vec<String>,
+
+    /// Keep stdin attached
+    pub interactive: bool,
+
+    /// Allocate a pseudo terminal
+    pub tty: bool,
+
+    /// Discard all output, used for checks
+    pub quiet: bool,
+
+    /// Extra arguments passed verbatim to the container manager
+    pub extra_args: Vec<String>,
+
+    pub command: Vec<String>,
+}
+
+/// Operations legumemanager needs from a container manager
+pub trait ContainerBackend {
+    /// Returns the container manager this backend talks to
+    fn manager(&self) -> ContainerManager;
+
+    /// Creates the container, does not start it
+    fn create(&self, opts: &CreateOptions) -> Result<()>;
+
+    /// Starts the container, does not wait for init to finish
+    fn start(&self, container_name: &str) -> Result<()>;
+
+    /// Asks the container to stop by sending it the signal, does not wait for it to stop
+    fn stop(&self, container_name: &str, signal: &str) -> Result<()>;
+
+    /// Kills the container using SIGKILL
+    fn kill(&self, container_name: &str) -> Result<()>;
+
+    /// Executes command inside the container with inherited stdio and returns its exit code
+    fn exec(&self, opts: &ExecOptions) -> Result<i32>;
+
+    /// Returns information about the container, None if it does not exist
+    fn inspect(&self, container_name: &str) -> Result<Option<ContainerInfo>>;
+
+    /// Returns names of all containers with the label (in 'key=value' format)
+    fn list(&self, label: &str) -> Result<Vec<String>>;
+
+    /// Copies file or directory from host into the container, it is copied into the destination
+    /// if it is an existing directory
+    fn cp(&self, source: &Path, container_name: &str, destination: &str) -> Result<()>;
+
+    /// Copies file or directory from the container to host
+    fn cp_from(&self, container_name: &str, source: &str, destination: &Path) -> Result<()>;
+
+    /// Removes the container, it has to be stopped beforehand
+    fn remove(&self, container_name: &str) -> Result<()>;
+
+    /// Returns reader of the container output with stdout and stderr combined, if follow is set
+    /// the reader waits for new output until the container stops
+    fn logs(&self, container_name: &str, follow: bool) -> Result<Box<dyn BufRead>>;
+
+    /// Waits until the container is not running anymore, returns false on timeout
+    ///
+    /// By default the state is polled, backends with access to events should use them instead
+    fn wait_for_stop(&self, container_name: &str, timeout: Duration) -> Result<bool> {
+        let start = Instant::now();
+
+        loop {
+            if !self.inspect(container_name)?.is_some_and(|x| x.is_running()) {
+                return Ok(true);
+            }
+
+            if start.elapsed() >= timeout {
+                return Ok(false);
+            }
+
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// Returns runner that executes commands of CLI backends, None if the real system is used
+fn get_mock_runner() -> Result<Option<Rc<dyn CommandRunner>>> {
+    #[cfg(debug_assertions)]
+    if let Some(path) = std::env::var_os(crate::env_vars::LM_MOCK_BACKEND) {
+        eprintln!("WARNING: using mock container manager, nothing will be executed\n");
+
+        let script = mock::MockScript::load(Path::new(&path))?;
+        return Ok(Some(Rc::new(mock::RecordingRunner::new(script))));
+    }
+
+    Ok(None)
+}
+
+/// Returns backend for the container manager, in dry run mode commands that change anything are
+/// printed instead of executed
+///
+/// API of the container manager is preferred when its socket is reachable, dry run always uses
+/// the executable so the commands can be printed
+pub fn get_backend(manager: ContainerManager, dry_run: bool) -> Result<Box<dyn ContainerBackend>> {
+    let mock_runner = get_mock_runner()?;
+    let use_api = !dry_run && mock_runner.is_none();
+    let runner = mock_runner.unwrap_or_else(|| Rc::new(cli::SystemRunner));
+
+    Ok(match manager {
+        ContainerManager::Podman => {
+            let cli = Podman::new(dry_run, runner);
+
+            match podman_api::get_socket_path().filter(|_| use_api) {
+                Some(socket) => match PodmanApi::connect(&socket, cli) {
+                    Ok(api) => Box::new(api),
+                    Err(cli) => Box::new(cli),
+                },
+                None => Box::new(cli),
+            }
+        },
+        ContainerManager::Docker => {
+            let cli = Docker::new(dry_run, runner);
+
+            match docker_api::get_socket_path().filter(|_| use_api) {
+                Some(socket) => match DockerApi::connect(&socket, cli) {
+                    Ok(api) => Box::new(api),
+                    Err(cli) => Box::new(cli),
+                },
+                None => Box::new(cli),
+            }
+        },
+    })
+}
@@ -0,0 +1,241 @@
+//! Parts of the Docker compatible REST API shared by Podman and Docker API backends, only the path
+//! prefix differs between them
+
+use super::archive;
+use super::attach::{attach, DemuxReader};
+use super::http::{encode_query, Response, UnixHttpClient};
+use super::{ContainerInfo, ExecOptions};
+use crate::{Context, Error, Result};
+use serde_json::json;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+
+/// Resolves env in 'KEY=value' format into pairs, variables without value are taken from host
+pub fn resolve_env(env: &[String]) -> Vec<(String, String)> {
+    env.iter()
+        .filter_map(|x| match x.split_once('=') {
+            Some((key, value)) => Some((key.to_string(), value.to_string())),
+            None => std::env::var(x).ok().map(|value| (x.to_string(), value)),
+        })
+        .collect()
+}
+
+/// Checks streamed output of image pull, errors are reported inside the stream not by status
+pub fn check_pull_response(image: &str, response: &Response) -> Result<()> {
+    if !response.is_success() {
+        return Err(response.error(&format!("failed to pull image '{}'", image)));
+    }
+
+    for line in String::from_utf8_lossy(&response.body).lines() {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(line) {
+            if let Some(error) = value.get("error").and_then(|x| x.as_str()) {
+                return Err(Error::msg(format!("failed to pull image '{}':\n{}", image, error)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct ApiClient {
+    pub http: UnixHttpClient,
+    prefix: &'static str,
+}
+
+impl ApiClient {
+    pub fn new(socket: &Path, prefix: &'static str) -> Self {
+        Self {
+            http: UnixHttpClient::new(socket),
+            prefix,
+        }
+    }
+
+    /// Returns full path of the endpoint
+    pub fn path(&self, path: &str) -> String {
+        format!("{}{}", self.prefix, path)
+    }
+
+    /// Checks if the API is reachable
+    pub fn ping(&self) -> bool {
+        self.http.request("GET", &self.path("/_ping"), None)
+            .is_ok_and(|x| x.is_success())
+    }
+
+    pub fn inspect(&self, container_name: &str) -> Result<Option<ContainerInfo>> {
+        let response = self.http.request("GET", &self.path(&format!("/containers/{}/json", encode_query(container_name))), None)?;
+
+        match response.status {
+            404 => Ok(None),
+            _ if response.is_success() => Ok(Some(ContainerInfo::from_json(response.json()?)?)),
+            _ => Err(response.error(&format!("failed to inspect container '{}'", container_name))),
+        }
+    }
+
+    pub fn list(&self, label: &str) -> Result<Vec<String>> {
+        let filters = json!({ "label": [label] }).to_string();
+        let response = self.http.request("GET", &self.path(&format!("/containers/json?all=true&filters={}", encode_query(&filters))), None)?;
+        if !response.is_success() {
+            return Err(response.error("failed to list containers"));
+        }
+
+        // docker prefixes the names with slash
+        let containers: Vec<serde_json::Value> = response.json()?;
+        Ok(containers.iter()
+            .filter_map(|x| x["Names"].get(0).and_then(|x| x.as_str()))
+            .map(|x| x.trim_start_matches('/').to_string())
+            .collect())
+    }
+
+    pub fn start(&self, container_name: &str) -> Result<()> {
+        let response = self.http.request("POST", &self.path(&format!("/containers/{}/start", encode_query(container_name))), None)?;
+
+        // 304 means it is already running
+        if !response.is_success() && response.status != 304 {
+            return Err(response.error(&format!("failed to start container '{}'", container_name)));
+        }
+
+        Ok(())
+    }
+
+    pub fn kill(&self, container_name: &str, signal: &str) -> Result<()> {
+        let response = self.http.request("POST", &self.path(&format!("/containers/{}/kill?signal={}", encode_query(container_name), encode_query(signal))), None)?;
+        if !response.is_success() {
+            return Err(response.error(&format!("failed to send signal to container '{}'", container_name)));
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&self, container_name: &str) -> Result<()> {
+        let response = self.http.request("DELETE", &self.path(&format!("/containers/{}", encode_query(container_name))), None)?;
+        if !response.is_success() {
+            return Err(response.error(&format!("failed to remove container '{}'", container_name)));
+        }
+
+        Ok(())
+    }
+
+    /// Streams container output, containers are created without tty so the output is multiplexed
+    pub fn logs(&self, container_name: &str, follow: bool) -> Result<Box<dyn BufRead>> {
+        let path = self.path(&format!("/containers/{}/logs?stdout=true&stderr=true&follow={}", encode_query(container_name), follow));
+        let (status, mut reader) = self.http.stream("GET", &path, None)?;
+
+        if !(200..300).contains(&status) {
+            let mut body = vec![];
+            reader.read_to_end(&mut body)?;
+
+            return Err(Response { status, body }.error(&format!("failed to get logs of container '{}'", container_name)));
+        }
+
+        Ok(Box::new(BufReader::new(DemuxReader::new(reader))))
+    }
+
+    /// Waits for exec session to finish and returns its exit code
+    fn wait_exec(&self, exec_id: &str) -> Result<i32> {
+        loop {
+            let response = self.http.request("GET", &self.path(&format!("/exec/{}/json", exec_id)), None)?;
+            if !response.is_success() {
+                return Err(response.error("failed to inspect exec session"));
+            }
+
+            let inspect: serde_json::Value = response.json()?;
+            if !inspect["Running"].as_bool().unwrap_or(false) {
+                return Ok(inspect["ExitCode"].as_i64().unwrap_or(0) as i32);
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Executes command with local stdio attached to the hijacked connection, extra arguments are
+    /// not supported
+    pub fn exec(&self, opts: &ExecOptions) -> Result<i32> {
+        let attach_output = !opts.quiet;
+        let attach_stdin = opts.interactive && !opts.quiet;
+
+        let response = self.http.request("POST", &self.path(&format!("/containers/{}/exec", encode_query(&opts.container_name))), Some(&json!({
+            "AttachStdin": attach_stdin,
+            "AttachStdout": attach_output,
+            "AttachStderr": attach_output,
+            "Tty": opts.tty,
+            "Cmd": opts.command,
+            "Env": opts.env,
+            "User": opts.user.clone().unwrap_or_default(),
+            "WorkingDir": opts.workdir.clone().unwrap_or_default(),
+        })))?;
+
+        if !response.is_success() {
+            return Err(response.error(&format!("failed to execute command in container '{}'", opts.container_name)));
+        }
+
+        let exec_id = response.json::<serde_json::Value>()?["Id"].as_str()
+            .context("exec session id missing from API response")?
+            .to_string();
+
+        let start_path = self.path(&format!("/exec/{}/start", exec_id));
+
+        if !attach_output {
+            let response = self.http.request("POST", &start_path, Some(&json!({ "Detach": true })))?;
+            if !response.is_success() {
+                return Err(response.error("failed to start exec session"));
+            }
+
+            return self.wait_exec(&exec_id);
+        }
+
+        let (status, stream) = self.http.upgrade("POST", &start_path, &json!({ "Detach": false, "Tty": opts.tty }))?;
+        if status != 200 && status != 101 {
+            return Err(Error::msg(format!("failed to start exec session (HTTP {})", status)));
+        }
+
+        let http = self.http.clone();
+        let resize_path = self.path(&format!("/exec/{}/resize", exec_id));
+        attach(stream, opts.tty, attach_stdin, move |rows, columns| {
+            let _ = http.request("POST", &format!("{}?h={}&w={}", resize_path, rows, columns), None);
+        })?;
+
+        self.wait_exec(&exec_id)
+    }
+
+    /// Extracts the archive into directory in the container, it has to exist
+    fn extract_archive(&self, container_name: &str, directory: &Path, archive: &[u8]) -> Result<Response> {
+        let path = self.path(&format!("/containers/{}/archive?path={}", encode_query(container_name), encode_query(&directory.to_string_lossy())));
+        self.http.request_raw("PUT", &path, Some(("application/x-tar", archive)))
+    }
+
+    /// Uploads file or directory into the container, it is copied into the destination if it is
+    /// an existing directory otherwise destination is the full path of the copy, same as `cp`
+    pub fn put_archive(&self, source: &Path, container_name: &str, destination: &str) -> Result<()> {
+        let destination = Path::new(destination);
+        let (Some(parent), Some(name)) = (destination.parent(), destination.file_name().and_then(|x| x.to_str())) else {
+            return Err(Error::msg(format!("invalid destination {:?}", destination)));
+        };
+
+        // extraction fails if the destination does not exist or is not a directory
+        if let Some(source_name) = source.file_name().and_then(|x| x.to_str()) {
+            if self.extract_archive(container_name, destination, &archive::pack_path(source, source_name)?)?.is_success() {
+                return Ok(());
+            }
+        }
+
+        let response = self.extract_archive(container_name, parent, &archive::pack_path(source, name)?)?;
+        if !response.is_success() {
+            return Err(response.error(&format!("failed to copy {:?} into container '{}'", source, container_name)));
+        }
+
+        Ok(())
+    }
+
+    /// Downloads file or directory from the container, destination is the full path of the copy
+    pub fn get_archive(&self, container_name: &str, source: &str, destination: &Path) -> Result<()> {
+        let path = self.path(&format!("/containers/{}/archive?path={}", encode_query(container_name), encode_query(source)));
+        let response = self.http.request_raw("GET", &path, None)?;
+        if !response.is_success() {
+            return Err(response.error(&format!("failed to copy '{}' from container '{}'", source, container_name)));
+        }
+
+        archive::unpack(&response.body, destination)
+    }
+}
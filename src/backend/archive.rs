@@ -0,0 +1,269 @@
+//! Minimal tar archive support for copying files through container manager APIs
+
+use crate::{Context, Error, Result};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Writes octal number into the header field, terminated by NUL
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[field.len() - 1] = 0;
+}
+
+/// Reads NUL terminated octal number from the header field
+fn read_octal(field: &[u8]) -> Result<u64> {
+    let text = String::from_utf8_lossy(field);
+    let text = text.trim_matches(|x: char| x == '\0' || x == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+
+    u64::from_str_radix(text, 8)
+        .with_context(|| format!("invalid number '{}' in tar header", text))
+}
+
+/// Reads NUL terminated string from the header field
+fn read_string(field: &[u8]) -> String {
+    let end = field.iter().position(|x| *x == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Appends ustar header of single entry, long names are split using the prefix field
+fn write_header(archive: &mut Vec<u8>, name: &str, mode: u32, size: u64, kind: u8, link: &str) -> Result<()> {
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name),
+        _ => name.char_indices()
+            .filter(|(i, x)| *x == '/' && *i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| (&name[..i], &name[i + 1..]))
+            .next()
+            .with_context(|| format!("path '{}' is too long for tar archive", name))?,
+    };
+
+    if link.len() > 100 {
+        return Err(Error::msg(format!("link target '{}' is too long for tar archive", link)));
+    }
+
+    let mut header = [0u8; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], (mode & 0o7777) as u64);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], 0);
+    header[156] = kind;
+    header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // checksum is calculated with the checksum field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|x| *x as u64).sum();
+    let digits = format!("{:06o}\0 ", checksum);
+    header[148..156].copy_from_slice(digits.as_bytes());
+
+    archive.extend_from_slice(&header);
+
+    Ok(())
+}
+
+/// Appends data padded to full block
+fn write_data(archive: &mut Vec<u8>, data: &[u8]) {
+    archive.extend_from_slice(data);
+
+    let padding = (BLOCK_SIZE - data.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    archive.resize(archive.len() + padding, 0);
+}
+
+/// Adds two empty blocks marking the end of archive
+fn finish(archive: &mut Vec<u8>) {
+    archive.resize(archive.len() + BLOCK_SIZE * 2, 0);
+}
+
+fn pack_entry(archive: &mut Vec<u8>, path: &Path, name: &str) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("failed to read metadata of {:?}", path))?;
+    let mode = metadata.permissions().mode();
+
+    if metadata.is_symlink() {
+        let target = std::fs::read_link(path)?;
+        write_header(archive, name, mode, 0, b'2', &target.to_string_lossy())?;
+    } else if metadata.is_dir() {
+        write_header(archive, &format!("{}/", name), mode, 0, b'5', "")?;
+
+        let mut entries = std::fs::read_dir(path)
+            .with_context(|| format!("failed to read directory {:?}", path))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|x| x.file_name());
+
+        for entry in entries {
+            pack_entry(archive, &entry.path(), &format!("{}/{}", name, entry.file_name().to_string_lossy()))?;
+        }
+    } else if metadata.is_file() {
+        let data = std::fs::read(path)
+            .with_context(|| format!("failed to read {:?}", path))?;
+        write_header(archive, name, mode, metadata.size(), b'0', "")?;
+        write_data(archive, &data);
+    } else {
+        return Err(Error::msg(format!("cannot copy special file {:?}", path)));
+    }
+
+    Ok(())
+}
+
+/// Creates ustar archive of a file or directory (recursively), the root entry is called 'name'
+pub fn pack_path(path: &Path, name: &str) -> Result<Vec<u8>> {
+    let mut archive = vec![];
+    pack_entry(&mut archive, path, name)?;
+    finish(&mut archive);
+
+    Ok(archive)
+}
+
+/// Parses PAX extended header records and returns the path if there is one
+fn parse_pax_path(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines()
+        .filter_map(|x| x.split_once(' ').map(|(_, record)| record))
+        .find_map(|x| x.strip_prefix("path=").map(|x| x.to_string()))
+}
+
+/// Checks that symlink at depth (number of directories between it and the destination) cannot
+/// point outside of the destination, '..' is only allowed at the start so it is never resolved
+/// through another symlink
+fn is_safe_link_target(depth: usize, target: &Path) -> bool {
+    let mut depth = depth;
+    let mut leading = true;
+
+    for component in target.components() {
+        match component {
+            Component::ParentDir if leading && depth > 0 => depth -= 1,
+            Component::CurDir => {},
+            Component::Normal(_) => leading = false,
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+/// Fails if the destination or any directory between it and the path is a symlink, writing
+/// through it could escape the destination
+fn ensure_no_symlink_parents(destination: &Path, relative: &Path) -> Result<()> {
+    let mut current = destination.to_path_buf();
+
+    for component in relative.components() {
+        if std::fs::symlink_metadata(&current).is_ok_and(|x| x.is_symlink()) {
+            return Err(Error::msg(format!("refusing to extract through symlink {:?}", current)));
+        }
+
+        current.push(component);
+    }
+
+    Ok(())
+}
+
+/// Extracts archive so its root entry becomes 'destination', paths and symlinks escaping it are
+/// refused
+pub fn unpack(data: &[u8], destination: &Path) -> Result<()> {
+    let mut offset = 0;
+    let mut long_name: Option<String> = None;
+
+    while offset + BLOCK_SIZE <= data.len() {
+        let header = &data[offset..offset + BLOCK_SIZE];
+        offset += BLOCK_SIZE;
+
+        // empty block marks the end
+        if header.iter().all(|x| *x == 0) {
+            break;
+        }
+
+        let size = read_octal(&header[124..136])? as usize;
+        let mode = read_octal(&header[100..108])? as u32;
+        let kind = header[156];
+
+        let content = data.get(offset..offset + size)
+            .context("tar archive is truncated")?;
+        offset += size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+
+        match kind {
+            // GNU long name, applies to the next entry
+            b'L' => {
+                long_name = Some(read_string(content));
+                continue;
+            },
+
+            // PAX extended header, only the path is of interest
+            b'x' => {
+                long_name = parse_pax_path(content);
+                continue;
+            },
+
+            // PAX global header
+            b'g' => continue,
+
+            _ => {},
+        }
+
+        let name = match long_name.take() {
+            Some(x) => x,
+            None => {
+                let prefix = read_string(&header[345..500]);
+                let name = read_string(&header[..100]);
+                if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+            },
+        };
+
+        // the root component is replaced by the destination
+        let mut relative = PathBuf::new();
+        let components = Path::new(&name).components()
+            .filter(|x| !matches!(x, Component::CurDir))
+            .skip(1);
+
+        for component in components {
+            match component {
+                Component::Normal(x) => relative.push(x),
+                _ => return Err(Error::msg(format!("refusing to extract unsafe path '{}'", name))),
+            }
+        }
+
+        ensure_no_symlink_parents(destination, &relative)?;
+        let path = destination.join(&relative);
+
+        match kind {
+            b'5' => std::fs::create_dir_all(&path)
+                .with_context(|| format!("failed to create directory {:?}", path))?,
+
+            b'2' => {
+                let target = PathBuf::from(read_string(&header[157..257]));
+                let depth = relative.components().count().saturating_sub(1);
+                if !is_safe_link_target(depth, &target) {
+                    return Err(Error::msg(format!("refusing to extract symlink '{}' pointing outside of the destination to {:?}", name, target)));
+                }
+
+                let _ = std::fs::remove_file(&path);
+                std::os::unix::fs::symlink(&target, &path)
+                    .with_context(|| format!("failed to create symlink {:?}", path))?;
+            },
+
+            b'0' | 0 => {
+                // a symlink left in place of the file would be followed
+                if std::fs::symlink_metadata(&path).is_ok_and(|x| x.is_symlink()) {
+                    return Err(Error::msg(format!("refusing to overwrite symlink {:?}", path)));
+                }
+
+                std::fs::write(&path, content)
+                    .with_context(|| format!("failed to write {:?}", path))?;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+            },
+
+            // devices and hard links are not needed
+            _ => eprintln!("Skipping unsupported entry '{}' in archive", name),
+        }
+    }
+
+    Ok(())
+}
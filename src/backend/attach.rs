@@ -0,0 +1,130 @@
+//! Attaching local stdio to a hijacked container manager API stream
+
+use crate::util::{get_window_size, RawTerminal};
+use crate::Result;
+use std::io::{ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Reads multiplexed output where each frame has 8 byte header with stream type and length
+fn demux_output(mut stream: UnixStream) -> Result<()> {
+    let mut header = [0u8; 8];
+    let mut buffer: Vec<u8> = vec![];
+
+    loop {
+        match stream.read_exact(&mut header) {
+            Ok(()) => {},
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+
+        let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        buffer.resize(size, 0);
+        stream.read_exact(&mut buffer)?;
+
+        // 1 is stdout, 2 is stderr
+        if header[0] == 2 {
+            let mut stderr = std::io::stderr();
+            stderr.write_all(&buffer)?;
+            stderr.flush()?;
+        } else {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(&buffer)?;
+            stdout.flush()?;
+        }
+    }
+}
+
+/// Reads multiplexed output as one stream, stdout and stderr frames are kept in order
+pub struct DemuxReader<R: Read> {
+    inner: R,
+
+    /// Bytes left in the current frame
+    remaining: usize,
+}
+
+impl<R: Read> DemuxReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, remaining: 0 }
+    }
+}
+
+impl<R: Read> Read for DemuxReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // skip empty frames
+        while self.remaining == 0 {
+            let mut header = [0u8; 8];
+            match self.inner.read_exact(&mut header) {
+                Ok(()) => {},
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(0),
+                Err(err) => return Err(err),
+            }
+
+            self.remaining = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        }
+
+        let size = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..size])?;
+        if read == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+/// Copies stdin into the stream and the stream into stdout and stderr until the stream is closed,
+/// resize is called whenever the terminal size changes
+pub fn attach<F>(stream: UnixStream, tty: bool, interactive: bool, resize: F) -> Result<()>
+where
+    F: Fn(u16, u16) + Send + 'static,
+{
+    // only makes sense if there is a terminal on this side as well
+    let _raw_terminal = if tty { RawTerminal::new(libc::STDIN_FILENO) } else { None };
+
+    let done = Arc::new(AtomicBool::new(false));
+
+    if tty {
+        let done = done.clone();
+        std::thread::spawn(move || {
+            let mut last_size = None;
+            while !done.load(Ordering::Relaxed) {
+                let size = get_window_size(libc::STDOUT_FILENO);
+                if let Some((rows, columns)) = size.filter(|_| size != last_size) {
+                    resize(rows, columns);
+                    last_size = size;
+                }
+
+                std::thread::sleep(Duration::from_millis(250));
+            }
+        });
+    }
+
+    if interactive {
+        let mut writer = stream.try_clone()?;
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut std::io::stdin(), &mut writer);
+
+            // let the process know there is no more input
+            let _ = writer.shutdown(Shutdown::Write);
+        });
+    }
+
+    let result = if tty {
+        let mut stream = stream;
+        let mut stdout = std::io::stdout();
+        std::io::copy(&mut stream, &mut stdout)
+            .map(|_| ())
+            .map_err(|x| x.into())
+    } else {
+        demux_output(stream)
+    };
+
+    done.store(true, Ordering::Relaxed);
+
+    result
+}
@@ -0,0 +1,288 @@
+//! Shared implementation of backends that execute the container manager executable
+
+use super::{ContainerInfo, CreateOptions, ExecOptions};
+use crate::{Context, Error, Result};
+use std::io::{BufRead, BufReader, PipeReader, Read};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::rc::Rc;
+
+/// Prints the command as it would be executed, used for dry run
+pub fn print_command(exe: &str, args: &[String]) {
+    print!("{}", exe);
+    for arg in args {
+        print!(" {}", arg);
+    }
+    println!();
+}
+
+/// Converts exit status to exit code, mimics the shell behaviour when killed by a signal
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(x) => x,
+        None => 128 + status.signal().unwrap_or(0),
+    }
+}
+
+/// Executes commands for CLI backends, abstracted so they can be recorded instead
+pub trait CommandRunner {
+    /// Runs the command capturing its output
+    fn output(&self, exe: &str, args: &[String]) -> Result<Output>;
+
+    /// Runs the command with inherited stdio (discarded if quiet) and returns its exit status
+    fn status(&self, exe: &str, args: &[String], quiet: bool) -> Result<ExitStatus>;
+
+    /// Runs the command returning reader of its stdout and stderr combined as it is written
+    fn stream(&self, exe: &str, args: &[String]) -> Result<Box<dyn BufRead>>;
+}
+
+/// Reads output of a running child, the child is killed when dropped
+struct ChildReader {
+    child: Child,
+    pipe: PipeReader,
+}
+
+impl Read for ChildReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.pipe.read(buf)
+    }
+}
+
+impl Drop for ChildReader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs the commands for real
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(&self, exe: &str, args: &[String]) -> Result<Output> {
+        Command::new(exe)
+            .args(args)
+            .output()
+            .with_context(|| format!("unable to execute manager '{}'", exe))
+    }
+
+    fn status(&self, exe: &str, args: &[String], quiet: bool) -> Result<ExitStatus> {
+        let mut command = Command::new(exe);
+        command.args(args);
+
+        if quiet {
+            command
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+        }
+
+        command.status()
+            .with_context(|| format!("unable to execute manager '{}'", exe))
+    }
+
+    fn stream(&self, exe: &str, args: &[String]) -> Result<Box<dyn BufRead>> {
+        let (pipe, writer) = std::io::pipe()
+            .with_context(|| "failed to create pipe")?;
+
+        let child = Command::new(exe)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(writer.try_clone()?)
+            .stderr(writer)
+            .spawn()
+            .with_context(|| format!("unable to execute manager '{}'", exe))?;
+
+        // the write ends were moved into the child so reading ends when it exits
+        Ok(Box::new(BufReader::new(ChildReader { child, pipe })))
+    }
+}
+
+/// Executes the container manager executable
+pub struct CliRunner {
+    pub exe: &'static str,
+    pub dry_run: bool,
+    pub runner: Rc<dyn CommandRunner>,
+}
+
+impl CliRunner {
+    /// Runs command that only reads state, so it is executed even in dry run
+    pub fn query(&self, args: &[String]) -> Result<Output> {
+        self.runner.output(self.exe, args)
+    }
+
+    /// Runs command that changes state, fails with stderr of the manager on error
+    pub fn run(&self, args: &[String], error_msg: &str) -> Result<()> {
+        if self.dry_run {
+            print_command(self.exe, args);
+            return Ok(());
+        }
+
+        let output = self.query(args)?;
+        if !output.status.success() {
+            return Err(Error::msg(format!("{}:\n{}", error_msg, String::from_utf8_lossy(&output.stderr).trim())));
+        }
+
+        Ok(())
+    }
+
+    /// Runs command with inherited stdio and returns its exit code
+    pub fn run_interactive(&self, args: &[String], quiet: bool) -> Result<i32> {
+        if self.dry_run {
+            print_command(self.exe, args);
+            return Ok(0);
+        }
+
+        Ok(exit_code(self.runner.status(self.exe, args, quiet)?))
+    }
+
+    /// Generates create arguments shared by all managers, container image is not included
+    pub fn create_args(&self, opts: &CreateOptions) -> Vec<String> {
+        let mut cmd: Vec<String> = vec![
+            "create".into(),
+            "--name".into(), opts.name.clone(),
+            "--hostname".into(), opts.hostname.clone(),
+            "--security-opt".into(), "label=disable".into(),
+            "--security-opt".into(), "apparmor=unconfined".into(),
+            "--user".into(), "root:root".into(),
+            "--network".into(), "host".into(),
+        ];
+
+        for (key, value) in &opts.labels {
+            cmd.extend(["--label".into(), format!("{}={}", key, value)]);
+        }
+
+        for i in &opts.env {
+            cmd.extend(["--env".into(), i.clone()]);
+        }
+
+        for i in &opts.volumes {
+            cmd.extend(["--volume".into(), i.clone()]);
+        }
+
+        for i in &opts.mounts {
+            cmd.extend(["--mount".into(), i.clone()]);
+        }
+
+        if let Some(signal) = &opts.stop_signal {
+            cmd.extend(["--stop-signal".into(), signal.clone()]);
+        }
+
+        cmd
+    }
+
+    /// Generates exec arguments, the same for all managers
+    pub fn exec_args(&self, opts: &ExecOptions) -> Vec<String> {
+        let mut cmd: Vec<String> = vec!["exec".into()];
+
+        if opts.interactive {
+            cmd.extend([
+                "--interactive".into(),
+                "--detach-keys=".into(),
+            ]);
+        }
+
+        if let Some(user) = &opts.user {
+            cmd.extend(["--user".into(), user.clone()]);
+        }
+
+        if let Some(workdir) = &opts.workdir {
+            cmd.push(format!("--workdir={}", workdir));
+        }
+
+        for i in &opts.env {
+            cmd.extend(["--env".into(), i.clone()]);
+        }
+
+        if opts.tty {
+            cmd.push("--tty".into());
+        }
+
+        cmd.extend(opts.extra_args.iter().cloned());
+        cmd.push(opts.container_name.clone());
+        cmd.extend(opts.command.iter().cloned());
+
+        cmd
+    }
+
+    pub fn start(&self, container_name: &str) -> Result<()> {
+        self.run(&["start".into(), container_name.into()],
+            &format!("failed to start container '{}'", container_name))
+    }
+
+    pub fn stop(&self, container_name: &str, signal: &str) -> Result<()> {
+        self.run(&["kill".into(), "--signal".into(), signal.into(), container_name.into()],
+            &format!("failed to send signal to container '{}'", container_name))
+    }
+
+    pub fn kill(&self, container_name: &str) -> Result<()> {
+        self.run(&["kill".into(), container_name.into()],
+            &format!("failed to kill container '{}'", container_name))
+    }
+
+    pub fn exec(&self, opts: &ExecOptions) -> Result<i32> {
+        self.run_interactive(&self.exec_args(opts), opts.quiet)
+    }
+
+    pub fn inspect(&self, container_name: &str) -> Result<Option<ContainerInfo>> {
+        let output = self.query(&["container".into(), "inspect".into(), container_name.into()])?;
+
+        // if it has failed then container probably does not exist
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let json: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("failed to parse inspect output of container '{}'", container_name))?;
+
+        json.into_iter().next().map(ContainerInfo::from_json).transpose()
+    }
+
+    pub fn list(&self, label: &str) -> Result<Vec<String>> {
+        let output = self.query(&[
+            "ps".into(), "--all".into(),
+            "--filter".into(), format!("label={}", label),
+            "--format".into(), "{{.Names}}".into(),
+        ])?;
+
+        if !output.status.success() {
+            return Err(Error::msg(format!("failed to list containers:\n{}", String::from_utf8_lossy(&output.stderr).trim())));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect())
+    }
+
+    pub fn cp(&self, source: &Path, container_name: &str, destination: &str) -> Result<()> {
+        let source = source.to_str().context("error converting path to &str")?;
+        self.run(&["container".into(), "cp".into(), source.into(), format!("{}:{}", container_name, destination)],
+            &format!("failed to copy {} into container '{}'", source, container_name))
+    }
+
+    pub fn cp_from(&self, container_name: &str, source: &str, destination: &Path) -> Result<()> {
+        let destination = destination.to_str().context("error converting path to &str")?;
+        self.run(&["container".into(), "cp".into(), format!("{}:{}", container_name, source), destination.into()],
+            &format!("failed to copy {} from container '{}'", source, container_name))
+    }
+
+    pub fn remove(&self, container_name: &str) -> Result<()> {
+        self.run(&["rm".into(), container_name.into()],
+            &format!("failed to remove container '{}'", container_name))
+    }
+
+    /// Only reads state so it is executed even in dry run
+    pub fn logs(&self, container_name: &str, follow: bool) -> Result<Box<dyn BufRead>> {
+        let mut args: Vec<String> = vec!["logs".into()];
+        if follow {
+            args.push("--follow".into());
+        }
+        args.push(container_name.into());
+
+        self.runner.stream(self.exe, &args)
+    }
+}
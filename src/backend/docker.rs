@@ -0,0 +1,97 @@
+//! Docker backend using the docker executable
+
+use super::cli::{CliRunner, CommandRunner};
+use super::{ContainerBackend, ContainerInfo, CreateOptions, ExecOptions};
+use crate::manager::ContainerManager;
+use crate::Result;
+use std::io::BufRead;
+use std::path::Path;
+use std::rc::Rc;
+
+pub struct Docker {
+    cli: CliRunner,
+}
+
+impl Docker {
+    pub fn new(dry_run: bool, runner: Rc<dyn CommandRunner>) -> Self {
+        Self {
+            cli: CliRunner {
+                exe: ContainerManager::Docker.get_executable_name(),
+                dry_run,
+                runner,
+            },
+        }
+    }
+
+    fn create_args(&self, opts: &CreateOptions) -> Vec<String> {
+        let mut cmd = self.cli.create_args(opts);
+
+        // systemd needs to manage its own cgroups
+        if opts.init {
+            cmd.push("--cgroupns=host".into());
+        }
+
+        cmd.extend(opts.extra_args.iter().cloned());
+
+        // docker only takes the executable as entrypoint, arguments go after the image
+        if let Some((exe, entrypoint_args)) = opts.entrypoint.split_first() {
+            cmd.extend(["--entrypoint".into(), exe.clone()]);
+            cmd.push(opts.image.clone());
+            cmd.extend(entrypoint_args.iter().cloned());
+        } else {
+            cmd.push(opts.image.clone());
+        }
+
+        cmd
+    }
+}
+
+impl ContainerBackend for Docker {
+    fn manager(&self) -> ContainerManager {
+        ContainerManager::Docker
+    }
+
+    fn create(&self, opts: &CreateOptions) -> Result<()> {
+        self.cli.run(&self.create_args(opts), "container creation failed")
+    }
+
+    fn start(&self, container_name: &str) -> Result<()> {
+        self.cli.start(container_name)
+    }
+
+    fn stop(&self, container_name: &str, signal: &str) -> Result<()> {
+        self.cli.stop(container_name, signal)
+    }
+
+    fn kill(&self, container_name: &str) -> Result<()> {
+        self.cli.kill(container_name)
+    }
+
+    fn exec(&self, opts: &ExecOptions) -> Result<i32> {
+        self.cli.exec(opts)
+    }
+
+    fn inspect(&self, container_name: &str) -> Result<Option<ContainerInfo>> {
+        self.cli.inspect(container_name)
+    }
+
+    fn list(&self, label: &str) -> Result<Vec<String>> {
+        self.cli.list(label)
+    }
+
+    fn cp(&self, source: &Path, container_name: &str, destination: &str) -> Result<()> {
+        self.cli.cp(source, container_name, destination)
+    }
+
+    fn cp_from(&self, container_name: &str, source: &str, destination: &Path) -> Result<()> {
+        self.cli.cp_from(container_name, source, destination)
+    }
+
+    fn remove(&self, container_name: &str) -> Result<()> {
+        self.cli.remove(container_name)
+    }
+
+    fn logs(&self, container_name: &str, follow: bool) -> Result<Box<dyn BufRead>> {
+        self.cli.logs(container_name, follow)
+    }
+}
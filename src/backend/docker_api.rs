@@ -0,0 +1,234 @@
+//! Docker backend using the Docker Engine REST API over unix socket, falls back to the docker
+//! executable for things the API cannot express like extra arguments
+
+#[cfg(test)]
+mod tests;
+
+use super::api::{check_pull_response, resolve_env, ApiClient};
+use super::http::encode_query;
+use super::{ContainerBackend, ContainerInfo, CreateOptions, Docker, ExecOptions};
+use crate::manager::ContainerManager;
+use crate::{Context, Error, Result};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{BufRead, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Prefix of all API endpoints, oldest version that has everything needed
+const API_PREFIX: &str = "/v1.41";
+
+/// Returns path to the docker API socket, honors DOCKER_HOST but only unix sockets are supported,
+/// socket of rootless docker is preferred if it exists
+pub fn get_socket_path() -> Option<PathBuf> {
+    match std::env::var("DOCKER_HOST") {
+        Ok(host) if !host.is_empty() => host.strip_prefix("unix://").map(PathBuf::from),
+        _ => std::env::var_os("XDG_RUNTIME_DIR")
+            .map(|x| PathBuf::from(x).join("docker.sock"))
+            .filter(|x| x.exists())
+            .or_else(|| Some(PathBuf::from("/var/run/docker.sock"))),
+    }
+}
+
+/// Parses '--mount' format (eg. 'type=tmpfs,destination=/tmp') into docker mount
+fn parse_mount(mount: &str) -> serde_json::Value {
+    let mut value = json!({ "Type": "bind" });
+
+    for option in mount.split(',') {
+        match option.split_once('=') {
+            Some(("type", x)) => value["Type"] = x.into(),
+            Some(("source" | "src", x)) => value["Source"] = x.into(),
+            Some(("destination" | "dst" | "target", x)) => value["Target"] = x.into(),
+            _ if option == "ro" || option == "readonly" => value["ReadOnly"] = true.into(),
+            _ => {},
+        }
+    }
+
+    value
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or_default()
+}
+
+pub struct DockerApi {
+    api: ApiClient,
+
+    /// Used for things the API cannot do
+    cli: Docker,
+}
+
+impl DockerApi {
+    /// Returns the backend if the API is reachable through the socket
+    pub fn connect(socket: &Path, cli: Docker) -> Result<Self, Docker> {
+        let api = ApiClient::new(socket, API_PREFIX);
+        if socket.exists() && api.ping() {
+            Ok(Self { api, cli })
+        } else {
+            Err(cli)
+        }
+    }
+
+    fn create_body(&self, opts: &CreateOptions) -> serde_json::Value {
+        let labels: HashMap<&str, &str> = opts.labels.iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+
+        let env: Vec<String> = resolve_env(&opts.env).into_iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+
+        let mounts: Vec<serde_json::Value> = opts.mounts.iter().map(|x| parse_mount(x)).collect();
+
+        let mut body = json!({
+            "Hostname": opts.hostname,
+            "Image": opts.image,
+            "User": "root:root",
+            "Env": env,
+            "Labels": labels,
+            "Entrypoint": opts.entrypoint,
+            "HostConfig": {
+                "NetworkMode": "host",
+                "Binds": opts.volumes,
+                "Mounts": mounts,
+                "SecurityOpt": ["label=disable", "apparmor=unconfined"],
+            },
+        });
+
+        if let Some(signal) = &opts.stop_signal {
+            body["StopSignal"] = signal.as_str().into();
+        }
+
+        // systemd needs to manage its own cgroups
+        if opts.init {
+            body["HostConfig"]["CgroupnsMode"] = "host".into();
+        }
+
+        body
+    }
+
+    fn is_running(&self, container_name: &str) -> Result<bool> {
+        Ok(self.api.inspect(container_name)?.is_some_and(|x| x.is_running()))
+    }
+}
+
+impl ContainerBackend for DockerApi {
+    fn manager(&self) -> ContainerManager {
+        ContainerManager::Docker
+    }
+
+    fn create(&self, opts: &CreateOptions) -> Result<()> {
+        // extra arguments can only be understood by the docker executable
+        if !opts.extra_args.is_empty() {
+            return self.cli.create(opts);
+        }
+
+        let body = self.create_body(opts);
+        let path = self.api.path(&format!("/containers/create?name={}", encode_query(&opts.name)));
+
+        let mut response = self.api.http.request("POST", &path, Some(&body))?;
+
+        // image does not exist locally, pull it like docker run does
+        if response.status == 404 {
+            let pull = self.api.http.request("POST", &self.api.path(&format!("/images/create?fromImage={}", encode_query(&opts.image))), None)?;
+            check_pull_response(&opts.image, &pull)?;
+
+            response = self.api.http.request("POST", &path, Some(&body))?;
+        }
+
+        if !response.is_success() {
+            return Err(response.error("container creation failed"));
+        }
+
+        Ok(())
+    }
+
+    fn start(&self, container_name: &str) -> Result<()> {
+        self.api.start(container_name)
+    }
+
+    fn stop(&self, container_name: &str, signal: &str) -> Result<()> {
+        self.api.kill(container_name, signal)
+    }
+
+    fn kill(&self, container_name: &str) -> Result<()> {
+        self.api.kill(container_name, "SIGKILL")
+    }
+
+    fn exec(&self, opts: &ExecOptions) -> Result<i32> {
+        // extra arguments can only be understood by the docker executable
+        if !opts.extra_args.is_empty() {
+            return self.cli.exec(opts);
+        }
+
+        self.api.exec(opts)
+    }
+
+    fn inspect(&self, container_name: &str) -> Result<Option<ContainerInfo>> {
+        self.api.inspect(container_name)
+    }
+
+    fn list(&self, label: &str) -> Result<Vec<String>> {
+        self.api.list(label)
+    }
+
+    fn cp(&self, source: &Path, container_name: &str, destination: &str) -> Result<()> {
+        self.api.put_archive(source, container_name, destination)
+    }
+
+    fn cp_from(&self, container_name: &str, source: &str, destination: &Path) -> Result<()> {
+        self.api.get_archive(container_name, source, destination)
+    }
+
+    fn remove(&self, container_name: &str) -> Result<()> {
+        self.api.remove(container_name)
+    }
+
+    fn logs(&self, container_name: &str, follow: bool) -> Result<Box<dyn BufRead>> {
+        self.api.logs(container_name, follow)
+    }
+
+    fn wait_for_stop(&self, container_name: &str, timeout: Duration) -> Result<bool> {
+        let since = unix_time();
+        let until = since + timeout.as_secs().max(1);
+        let filters = json!({
+            "type": ["container"],
+            "container": [container_name],
+            "event": ["die"],
+        }).to_string();
+
+        // the server closes the stream once 'until' passes, read timeout is just a safeguard
+        let (status, reader) = self.api.http.stream(
+            "GET",
+            &self.api.path(&format!("/events?since={}&until={}&filters={}", since, until, encode_query(&filters))),
+            Some(timeout + Duration::from_secs(2)),
+        )?;
+
+        if !(200..300).contains(&status) {
+            return Err(Error::msg(format!("failed to subscribe to events (HTTP {})", status)));
+        }
+
+        // the container could have stopped before the subscription
+        if !self.is_running(container_name)? {
+            return Ok(true);
+        }
+
+        for line in reader.lines() {
+            let line = match line {
+                Ok(x) => x,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(err) => return Err(err).context("failed to read events"),
+            };
+
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(&line) else {
+                continue;
+            };
+
+            if event["Action"].as_str() == Some("die") {
+                return Ok(true);
+            }
+        }
+
+        Ok(!self.is_running(container_name)?)
+    }
+}
@@ -0,0 +1,209 @@
+//! Docker API backend talking to a stand-in server on a local unix socket
+
+use super::DockerApi;
+use crate::backend::archive::pack_path;
+use crate::backend::cli::SystemRunner;
+use crate::backend::stand_in::StandIn;
+use crate::backend::{ContainerBackend, CreateOptions, Docker};
+use std::path::Path;
+use std::rc::Rc;
+
+fn docker(stand_in: &StandIn) -> DockerApi {
+    let cli = Docker::new(false, Rc::new(SystemRunner));
+    DockerApi::connect(&stand_in.socket(), cli)
+        .unwrap_or_else(|_| panic!("stand-in did not answer ping"))
+}
+
+/// Joins archives into one, the end marker is only kept in the last one
+fn concat_archives(archives: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = vec![];
+    for i in archives {
+        result.truncate(result.len().saturating_sub(1024));
+        result.extend_from_slice(i);
+    }
+
+    result
+}
+
+/// Serves the archive for every archive download
+fn serve_archive(name: &str, archive: Vec<u8>) -> StandIn {
+    StandIn::new(name, Box::new(move |request| match request.method.as_str() {
+        "GET" if request.path.contains("/archive?") => (200, archive.clone()),
+        _ => (404, b"{\"message\":\"not found\"}".to_vec()),
+    }))
+}
+
+fn write_file(path: &Path, data: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, data).unwrap();
+}
+
+#[test]
+fn create_with_labels_and_mounts() {
+    let stand_in = StandIn::new("create", Box::new(|_| (201, b"{\"Id\":\"abc\"}".to_vec())));
+
+    docker(&stand_in).create(&CreateOptions {
+        name: "lm-test".into(),
+        image: "alpine".into(),
+        hostname: "pet".into(),
+        init: true,
+        labels: vec![("manager".into(), "legumemanager".into())],
+        volumes: vec!["/a:/b:ro".into()],
+        mounts: vec!["type=tmpfs,destination=/tmp".into()],
+        entrypoint: vec!["/lm".into(), "init".into()],
+        stop_signal: Some("SIGRTMIN+3".into()),
+        ..Default::default()
+    }).unwrap();
+
+    let requests = stand_in.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].path, "/v1.41/containers/create?name=lm-test");
+
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["Image"], "alpine");
+    assert_eq!(body["Hostname"], "pet");
+    assert_eq!(body["Labels"]["manager"], "legumemanager");
+    assert_eq!(body["Entrypoint"], serde_json::json!(["/lm", "init"]));
+    assert_eq!(body["StopSignal"], "SIGRTMIN+3");
+    assert_eq!(body["HostConfig"]["Binds"], serde_json::json!(["/a:/b:ro"]));
+    assert_eq!(body["HostConfig"]["Mounts"], serde_json::json!([{ "Type": "tmpfs", "Target": "/tmp" }]));
+    assert_eq!(body["HostConfig"]["CgroupnsMode"], "host");
+}
+
+#[test]
+fn inspect_missing_container() {
+    let stand_in = StandIn::new("inspect", Box::new(|_| (404, b"{\"message\":\"No such container\"}".to_vec())));
+
+    assert!(docker(&stand_in).inspect("lm-missing").unwrap().is_none());
+    assert_eq!(stand_in.requests()[0].path, "/v1.41/containers/lm-missing/json");
+}
+
+/// Accepts archives only for the directories like the API does for existing directories
+fn accept_archives_into(name: &str, directories: &'static [&'static str]) -> StandIn {
+    StandIn::new(name, Box::new(move |request| {
+        let path = request.path.split_once("?path=").map(|(_, x)| x.replace("%2F", "/")).unwrap_or_default();
+        match request.method.as_str() {
+            "PUT" if directories.contains(&path.as_str()) => (200, vec![]),
+            _ => (404, b"{\"message\":\"Could not find the file\"}".to_vec()),
+        }
+    }))
+}
+
+/// Returns name of the first entry in the archive
+fn root_entry(archive: &[u8]) -> String {
+    String::from_utf8_lossy(&archive[..100]).trim_end_matches('\0').to_string()
+}
+
+#[test]
+fn push_directory() {
+    let stand_in = accept_archives_into("push", &["/home/user"]);
+    let source = stand_in.dir.join("source");
+    write_file(&source.join("a"), "first");
+    write_file(&source.join("sub/b"), "second");
+
+    docker(&stand_in).cp(&source, "lm-test", "/home/user/copy").unwrap();
+
+    // destination does not exist so it becomes the copy
+    let requests = stand_in.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/v1.41/containers/lm-test/archive?path=%2Fhome%2Fuser%2Fcopy");
+    assert_eq!(requests[1].method, "PUT");
+    assert_eq!(requests[1].path, "/v1.41/containers/lm-test/archive?path=%2Fhome%2Fuser");
+    assert!(root_entry(&requests[1].body).starts_with("copy"));
+
+    // the uploaded archive extracts into the same tree
+    let copy = stand_in.dir.join("copy");
+    crate::backend::archive::unpack(&requests[1].body, &copy).unwrap();
+    assert_eq!(std::fs::read_to_string(copy.join("a")).unwrap(), "first");
+    assert_eq!(std::fs::read_to_string(copy.join("sub/b")).unwrap(), "second");
+}
+
+#[test]
+fn push_into_existing_directory() {
+    let stand_in = accept_archives_into("push-into", &["/home/user"]);
+    let source = stand_in.dir.join("source");
+    write_file(&source.join("a"), "first");
+
+    docker(&stand_in).cp(&source, "lm-test", "/home/user").unwrap();
+
+    // same as 'cp' the copy is placed inside
+    let requests = stand_in.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/v1.41/containers/lm-test/archive?path=%2Fhome%2Fuser");
+    assert!(root_entry(&requests[0].body).starts_with("source"));
+}
+
+#[test]
+fn push_into_missing_container() {
+    let stand_in = accept_archives_into("push-missing", &[]);
+    let source = stand_in.dir.join("file");
+    write_file(&source, "data");
+
+    let err = docker(&stand_in).cp(&source, "lm-test", "/file").unwrap_err();
+    assert!(err.to_string().contains("HTTP 404"), "{:#}", err);
+}
+
+#[test]
+fn pull_directory() {
+    let source = std::env::temp_dir().join(format!("lm-docker-test-{}-pull-source", std::process::id()));
+    let _ = std::fs::remove_dir_all(&source);
+    write_file(&source.join("sub/file"), "data");
+    std::os::unix::fs::symlink("sub/file", source.join("link")).unwrap();
+    std::os::unix::fs::symlink("../link", source.join("sub/up")).unwrap();
+
+    let archive = pack_path(&source, "source").unwrap();
+    std::fs::remove_dir_all(&source).unwrap();
+
+    let stand_in = serve_archive("pull", archive);
+    let destination = stand_in.dir.join("copy");
+    docker(&stand_in).cp_from("lm-test", "/home/user/source", &destination).unwrap();
+
+    assert_eq!(stand_in.requests()[0].path, "/v1.41/containers/lm-test/archive?path=%2Fhome%2Fuser%2Fsource");
+    assert_eq!(std::fs::read_to_string(destination.join("sub/file")).unwrap(), "data");
+    assert_eq!(std::fs::read_link(destination.join("link")).unwrap(), Path::new("sub/file"));
+    assert_eq!(std::fs::read_to_string(destination.join("sub/up")).unwrap(), "data");
+}
+
+#[test]
+fn pull_refuses_escaping_symlinks() {
+    for (name, target) in [("absolute", "/etc"), ("relative", "../outside"), ("nested", "sub/../../outside")] {
+        let source = std::env::temp_dir().join(format!("lm-docker-test-{}-escape-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&source);
+        std::fs::create_dir_all(source.join("sub")).unwrap();
+        std::os::unix::fs::symlink(target, source.join("link")).unwrap();
+
+        let archive = pack_path(&source, "source").unwrap();
+        std::fs::remove_dir_all(&source).unwrap();
+
+        let stand_in = serve_archive(&format!("escape-{}", name), archive);
+        let destination = stand_in.dir.join("copy");
+
+        assert!(docker(&stand_in).cp_from("lm-test", "/source", &destination).is_err(), "symlink to {:?} was extracted", target);
+        assert!(std::fs::symlink_metadata(destination.join("link")).is_err());
+    }
+}
+
+#[test]
+fn pull_refuses_writing_through_symlink() {
+    let source = std::env::temp_dir().join(format!("lm-docker-test-{}-through-source", std::process::id()));
+    let _ = std::fs::remove_dir_all(&source);
+    std::fs::create_dir_all(source.join("sub")).unwrap();
+    std::os::unix::fs::symlink("sub", source.join("link")).unwrap();
+    write_file(&source.join("keys"), "ssh-ed25519 AAAA");
+
+    // an archive from a container can have entries below a symlink, which a real tree cannot
+    let archive = concat_archives(&[
+        pack_path(&source.join("sub"), "source").unwrap(),
+        pack_path(&source.join("link"), "source/link").unwrap(),
+        pack_path(&source.join("keys"), "source/link/authorized_keys").unwrap(),
+    ]);
+    std::fs::remove_dir_all(&source).unwrap();
+
+    let stand_in = serve_archive("through", archive);
+    let destination = stand_in.dir.join("copy");
+
+    let err = docker(&stand_in).cp_from("lm-test", "/source", &destination).unwrap_err();
+    assert!(err.to_string().contains("through symlink"), "{:#}", err);
+    assert!(!destination.join("sub/authorized_keys").exists());
+}
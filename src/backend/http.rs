@@ -0,0 +1,279 @@
+//! Minimal HTTP/1.1 client over unix socket used to talk to container manager APIs
+
+use crate::{Context, Error, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Response with the whole body read
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Parses the body as JSON
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_slice(&self.body)
+            .with_context(|| "failed to parse API response")
+    }
+
+    /// Turns the response into error, using the error message from API if there is one
+    pub fn error(&self, error_msg: &str) -> Error {
+        let message = serde_json::from_slice::<serde_json::Value>(&self.body).ok()
+            .and_then(|x| x.get("message").and_then(|x| x.as_str()).map(|x| x.to_string()))
+            .unwrap_or_else(|| String::from_utf8_lossy(&self.body).trim().to_string());
+
+        Error::msg(format!("{} (HTTP {}):\n{}", error_msg, self.status, message))
+    }
+}
+
+/// Percent encodes string for use in URL query
+pub fn encode_query(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+/// Reader decoding chunked transfer encoding as the data arrives, used for streamed responses
+pub struct ChunkedReader<R: BufRead> {
+    inner: R,
+    remaining: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.finished || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            let mut line = String::new();
+            self.inner.read_line(&mut line)?;
+
+            // skip CRLF ending the previous chunk
+            if line.trim().is_empty() {
+                line.clear();
+                self.inner.read_line(&mut line)?;
+            }
+
+            self.remaining = usize::from_str_radix(line.trim().split(';').next().unwrap_or_default(), 16)
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid chunk size '{}'", line.trim())))?;
+
+            if self.remaining == 0 {
+                self.finished = true;
+                return Ok(0);
+            }
+        }
+
+        let size = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..size])?;
+        if read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.remaining -= read;
+
+        Ok(read)
+    }
+}
+
+#[derive(Clone)]
+pub struct UnixHttpClient {
+    socket: PathBuf,
+}
+
+impl UnixHttpClient {
+    pub fn new(socket: &Path) -> Self {
+        Self {
+            socket: socket.to_path_buf(),
+        }
+    }
+
+    fn connect(&self) -> Result<UnixStream> {
+        UnixStream::connect(&self.socket)
+            .with_context(|| format!("failed to connect to socket {:?}", self.socket))
+    }
+
+    fn write_request(stream: &mut UnixStream, method: &str, path: &str, body: Option<(&str, &[u8])>, upgrade: bool) -> Result<()> {
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, path);
+
+        if upgrade {
+            head.push_str("Connection: Upgrade\r\nUpgrade: tcp\r\n");
+        } else {
+            head.push_str("Connection: close\r\n");
+        }
+
+        if let Some((content_type, data)) = body {
+            head.push_str(&format!("Content-Type: {}\r\nContent-Length: {}\r\n", content_type, data.len()));
+        } else {
+            head.push_str("Content-Length: 0\r\n");
+        }
+
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        if let Some((_, data)) = body {
+            stream.write_all(data)?;
+        }
+        stream.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads status line and headers, header names are lowercase
+    fn read_head<R: BufRead>(reader: &mut R) -> Result<(u16, HashMap<String, String>)> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+
+        let status = line.split_whitespace()
+            .nth(1)
+            .and_then(|x| x.parse::<u16>().ok())
+            .with_context(|| format!("invalid HTTP status line '{}'", line.trim()))?;
+
+        let mut headers = HashMap::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some((key, value)) = line.split_once(':') {
+                headers.insert(key.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+
+        Ok((status, headers))
+    }
+
+    fn read_body<R: BufRead>(reader: &mut R, headers: &HashMap<String, String>) -> Result<Vec<u8>> {
+        let mut body = vec![];
+
+        if headers.get("transfer-encoding").is_some_and(|x| x.eq_ignore_ascii_case("chunked")) {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line)?;
+
+                let size = usize::from_str_radix(line.trim().split(';').next().unwrap_or_default(), 16)
+                    .with_context(|| format!("invalid chunk size '{}'", line.trim()))?;
+
+                if size == 0 {
+                    break;
+                }
+
+                let start = body.len();
+                body.resize(start + size, 0);
+                reader.read_exact(&mut body[start..])?;
+
+                // skip CRLF after the chunk
+                line.clear();
+                reader.read_line(&mut line)?;
+            }
+        } else if let Some(length) = headers.get("content-length").and_then(|x| x.parse::<usize>().ok()) {
+            body.resize(length, 0);
+            reader.read_exact(&mut body)?;
+        } else {
+            reader.read_to_end(&mut body)?;
+        }
+
+        Ok(body)
+    }
+
+    /// Sends request with raw body and reads the whole response
+    pub fn request_raw(&self, method: &str, path: &str, body: Option<(&str, &[u8])>) -> Result<Response> {
+        let mut stream = self.connect()?;
+        Self::write_request(&mut stream, method, path, body, false)
+            .with_context(|| format!("failed to send request {} {}", method, path))?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = Self::read_head(&mut reader)
+            .with_context(|| format!("failed to read response of {} {}", method, path))?;
+        let body = Self::read_body(&mut reader, &headers)
+            .with_context(|| format!("failed to read response of {} {}", method, path))?;
+
+        Ok(Response { status, body })
+    }
+
+    /// Sends request with optional JSON body and reads the whole response
+    pub fn request(&self, method: &str, path: &str, body: Option<&serde_json::Value>) -> Result<Response> {
+        match body {
+            Some(json) => {
+                let data = serde_json::to_vec(json)?;
+                self.request_raw(method, path, Some(("application/json", &data)))
+            },
+            None => self.request_raw(method, path, None),
+        }
+    }
+
+    /// Sends request and returns reader of the body as it arrives, used for streamed responses like
+    /// events, reading fails after the timeout passes without any data
+    pub fn stream(&self, method: &str, path: &str, timeout: Option<Duration>) -> Result<(u16, Box<dyn BufRead>)> {
+        let mut stream = self.connect()?;
+        stream.set_read_timeout(timeout)?;
+        Self::write_request(&mut stream, method, path, None, false)
+            .with_context(|| format!("failed to send request {} {}", method, path))?;
+
+        let mut reader = BufReader::new(stream);
+        let (status, headers) = Self::read_head(&mut reader)
+            .with_context(|| format!("failed to read response of {} {}", method, path))?;
+
+        if headers.get("transfer-encoding").is_some_and(|x| x.eq_ignore_ascii_case("chunked")) {
+            Ok((status, Box::new(BufReader::new(ChunkedReader::new(reader)))))
+        } else {
+            Ok((status, Box::new(reader)))
+        }
+    }
+
+    /// Sends request and returns the stream after the response head, used for attaching to
+    /// containers where the connection is hijacked
+    pub fn upgrade(&self, method: &str, path: &str, body: &serde_json::Value) -> Result<(u16, UnixStream)> {
+        let mut stream = self.connect()?;
+        let data = serde_json::to_vec(body)?;
+        Self::write_request(&mut stream, method, path, Some(("application/json", &data)), true)
+            .with_context(|| format!("failed to send request {} {}", method, path))?;
+
+        // read the head byte by byte so nothing from the raw stream is buffered away
+        let mut head: Vec<u8> = vec![];
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            if stream.read(&mut byte)? == 0 {
+                return Err(Error::msg(format!("connection closed while reading response of {} {}", method, path)));
+            }
+            head.push(byte[0]);
+        }
+
+        let (status, _) = Self::read_head(&mut head.as_slice())?;
+
+        Ok((status, stream))
+    }
+}
@@ -0,0 +1,246 @@
+//! Typed container inspect output, the fields are named the same in Podman and Docker so one model
+//! is used for both with the differences normalized during parsing
+
+use crate::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+/// Treats null the same as missing value, managers often use null for empty lists and maps
+fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Accepts both string and number, podman reports some values as numbers where docker uses strings
+fn string_or_number<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(x)) if !x.is_empty() => Some(x),
+        Some(serde_json::Value::Number(x)) => Some(x.to_string()),
+        _ => None,
+    })
+}
+
+/// Docker prefixes container names with slash
+fn container_name<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name: String = null_as_default(deserializer)?;
+    Ok(name.trim_start_matches('/').to_string())
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct ContainerState {
+    /// State of the container (eg. 'created', 'running', 'exited')
+    #[serde(deserialize_with = "null_as_default")]
+    pub status: String,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub pid: i64,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub exit_code: i32,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub started_at: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct ContainerConfig {
+    #[serde(deserialize_with = "null_as_default")]
+    pub hostname: String,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub image: String,
+
+    /// Environment variables in 'KEY=value' format
+    #[serde(deserialize_with = "null_as_default")]
+    pub env: Vec<String>,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub labels: HashMap<String, String>,
+
+    /// Signal used to stop the container, either name or number
+    #[serde(deserialize_with = "string_or_number")]
+    pub stop_signal: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct MountInfo {
+    /// Type of the mount (eg. 'bind', 'tmpfs', 'volume')
+    #[serde(deserialize_with = "null_as_default")]
+    pub r#type: String,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub source: String,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub destination: String,
+
+    #[serde(rename = "RW", deserialize_with = "null_as_default")]
+    pub rw: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct HostConfig {
+    #[serde(deserialize_with = "null_as_default")]
+    pub network_mode: String,
+
+    /// Volumes in 'source:destination:options' format
+    #[serde(deserialize_with = "null_as_default")]
+    pub binds: Vec<String>,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub security_opt: Vec<String>,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub cgroupns_mode: String,
+}
+
+/// Container inspect output of both Podman and Docker
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct ContainerInfo {
+    #[serde(deserialize_with = "null_as_default")]
+    pub id: String,
+
+    #[serde(deserialize_with = "container_name")]
+    pub name: String,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub state: ContainerState,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub config: ContainerConfig,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub mounts: Vec<MountInfo>,
+
+    #[serde(deserialize_with = "null_as_default")]
+    pub host_config: HostConfig,
+}
+
+impl ContainerInfo {
+    /// Parses raw inspect JSON of a single container
+    pub fn from_json(value: serde_json::Value) -> Result<Self> {
+        let mut info: Self = serde_json::from_value(value)
+            .with_context(|| "failed to parse container inspect output")?;
+
+        // podman has few extra states that mean the same as in docker
+        info.state.status = match info.state.status.to_lowercase().as_str() {
+            "configured" => "created".into(),
+            "stopped" => "exited".into(),
+            x => x.into(),
+        };
+
+        Ok(info)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.status == "running"
+    }
+
+    /// Returns value of the label
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.config.labels.get(key).map(|x| x.as_str())
+    }
+
+    /// Returns true if the label is set to 'true'
+    pub fn label_is_true(&self, key: &str) -> bool {
+        self.label(key) == Some("true")
+    }
+
+    /// Returns value of the environment variable
+    pub fn env_var(&self, key: &str) -> Option<&str> {
+        self.config.env.iter()
+            .filter_map(|x| x.split_once('='))
+            .find(|(x, _)| *x == key)
+            .map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn podman_inspect() {
+        let info = ContainerInfo::from_json(json!({
+            "Id": "8d1f",
+            "Name": "pet",
+            "State": { "Status": "configured", "Pid": 0, "ExitCode": 0, "StartedAt": "0001-01-01T00:00:00Z" },
+            "Config": {
+                "Hostname": "pet",
+                "Image": "docker.io/library/alpine:latest",
+                "Env": ["HOME=/home/test", "TERM=xterm", "EMPTY="],
+                "Labels": { "manager": "legumemanager" },
+                "StopSignal": 37,
+            },
+            "Mounts": [{ "Type": "bind", "Source": "/home/test", "Destination": "/home/test", "RW": true }],
+            "HostConfig": { "NetworkMode": "host", "Binds": null, "SecurityOpt": null, "CgroupnsMode": "host" },
+        })).unwrap();
+
+        assert_eq!(info.name, "pet");
+        assert_eq!(info.state.status, "created");
+        assert_eq!(info.config.stop_signal.as_deref(), Some("37"));
+        assert_eq!(info.label("manager"), Some("legumemanager"));
+        assert_eq!(info.env_var("HOME"), Some("/home/test"));
+        assert_eq!(info.env_var("EMPTY"), Some(""));
+        assert_eq!(info.env_var("MISSING"), None);
+        assert!(info.mounts[0].rw);
+        assert!(info.host_config.binds.is_empty());
+    }
+
+    #[test]
+    fn docker_inspect() {
+        let info = ContainerInfo::from_json(json!({
+            "Id": "8d1f",
+            "Name": "/pet",
+            "State": { "Status": "exited", "Pid": 0, "ExitCode": 137 },
+            "Config": {
+                "Hostname": "pet",
+                "Env": null,
+                "Labels": null,
+                "StopSignal": "SIGRTMIN+3",
+            },
+            "Mounts": null,
+            "HostConfig": null,
+        })).unwrap();
+
+        assert_eq!(info.name, "pet");
+        assert_eq!(info.state.exit_code, 137);
+        assert_eq!(info.config.stop_signal.as_deref(), Some("SIGRTMIN+3"));
+        assert!(info.config.env.is_empty());
+        assert!(info.config.labels.is_empty());
+        assert!(info.mounts.is_empty());
+        assert!(!info.is_running());
+    }
+
+    #[test]
+    fn podman_states() {
+        for (status, expected) in [("configured", "created"), ("stopped", "exited"), ("Running", "running"), ("paused", "paused")] {
+            let info = ContainerInfo::from_json(json!({ "State": { "Status": status } })).unwrap();
+            assert_eq!(info.state.status, expected);
+        }
+    }
+
+    #[test]
+    fn missing_and_empty_values() {
+        let info = ContainerInfo::from_json(json!({ "Name": null, "Config": { "StopSignal": "" } })).unwrap();
+        assert_eq!(info.name, "");
+        assert_eq!(info.config.stop_signal, None);
+
+        assert!(ContainerInfo::from_json(json!({ "Config": { "Env": "HOME=/" } })).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Recording runner used instead of the real container manager, commands are printed to stderr
+//! and answered from a script so host side flows can be tried without a container engine
+
+use super::cli::CommandRunner;
+use crate::Result;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, Cursor};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+
+/// Scripted state of the fake container manager
+#[derive(Deserialize, Debug, Default)]
+pub struct MockScript {
+    /// Inspect output of existing containers by name
+    #[serde(default)]
+    pub containers: HashMap<String, serde_json::Value>,
+
+    /// Exit code returned by every exec, defaults to 0
+    #[serde(default)]
+    pub exec_exit_code: i32,
+
+    /// Output returned by logs by container name
+    #[serde(default)]
+    pub logs: HashMap<String, String>,
+}
+
+impl MockScript {
+    /// Loads the script from a JSON file
+    #[cfg(debug_assertions)]
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        let mut script: Self = crate::util::load_json(path, "mock script")?;
+
+        // real managers always report the name
+        for (name, inspect) in script.containers.iter_mut() {
+            if inspect.get("Name").is_none() {
+                inspect["Name"] = name.as_str().into();
+            }
+        }
+
+        Ok(script)
+    }
+}
+
+/// Records every command and answers it from the script, the state of containers changes
+/// accordingly so whole flows like create, start and destroy work
+pub struct RecordingRunner {
+    script: RefCell<MockScript>,
+
+    /// Every command executed so far, starting with the executable
+    commands: RefCell<Vec<Vec<String>>>,
+}
+
+fn output(code: i32, stdout: String, stderr: String) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: stdout.into_bytes(),
+        stderr: stderr.into_bytes(),
+    }
+}
+
+/// Finds value of the flag in the arguments, both '--flag value' and '--flag=value' forms
+fn find_flag_values<'a>(args: &'a [String], flag: &str) -> Vec<&'a str> {
+    let mut values = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == flag {
+            if let Some(value) = iter.next() {
+                values.push(value.as_str());
+            }
+        } else if let Some(value) = arg.strip_prefix(flag).and_then(|x| x.strip_prefix('=')) {
+            values.push(value);
+        }
+    }
+
+    values
+}
+
+impl RecordingRunner {
+    pub fn new(script: MockScript) -> Self {
+        Self {
+            script: RefCell::new(script),
+            commands: RefCell::new(vec![]),
+        }
+    }
+
+    /// Returns commands recorded so far
+    #[cfg(test)]
+    pub fn commands(&self) -> Vec<Vec<String>> {
+        self.commands.borrow().clone()
+    }
+
+    fn record(&self, exe: &str, args: &[String]) {
+        eprintln!("[mock] {} {}", exe, args.join(" "));
+        self.commands.borrow_mut().push([&[exe.to_string()], args].concat());
+    }
+
+    fn set_state(&self, container_name: &str, state: &str) -> bool {
+        match self.script.borrow_mut().containers.get_mut(container_name) {
+            Some(container) => {
+                container["State"] = serde_json::json!({ "Status": state });
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn create(&self, args: &[String]) -> Output {
+        let name = find_flag_values(args, "--name").first().map(|x| x.to_string()).unwrap_or_default();
+        if self.script.borrow().containers.contains_key(&name) {
+            return output(125, String::new(), format!("container name '{}' is already in use", name));
+        }
+
+        let labels: serde_json::Map<String, serde_json::Value> = find_flag_values(args, "--label").iter()
+            .filter_map(|x| x.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.into()))
+            .collect();
+
+        let inspect = serde_json::json!({
+            "Id": format!("mock-{}", name),
+            "Name": name,
+            "State": { "Status": "created" },
+            "Config": {
+                "Hostname": find_flag_values(args, "--hostname").first().copied().unwrap_or_default(),
+                "Image": args.last().cloned().unwrap_or_default(),
+                "Env": find_flag_values(args, "--env"),
+                "Labels": labels,
+            },
+        });
+
+        self.script.borrow_mut().containers.insert(name, inspect);
+
+        output(0, String::new(), String::new())
+    }
+
+    fn answer(&self, args: &[String]) -> Output {
+        let last = args.last().map(|x| x.as_str()).unwrap_or_default();
+        let not_found = || output(125, String::new(), format!("no such container {}", last));
+
+        match args.first().map(|x| x.as_str()) {
+            Some("container") if args.get(1).map(|x| x.as_str()) == Some("inspect") => {
+                match self.script.borrow().containers.get(last) {
+                    Some(x) => output(0, serde_json::json!([x]).to_string(), String::new()),
+                    None => not_found(),
+                }
+            },
+            Some("ps") => {
+                let label = find_flag_values(args, "--filter").iter()
+                    .find_map(|x| x.strip_prefix("label="))
+                    .and_then(|x| x.split_once('='));
+
+                let names: Vec<String> = self.script.borrow().containers.iter()
+                    .filter(|(_, inspect)| match label {
+                        Some((key, value)) => inspect.pointer(&format!("/Config/Labels/{}", key)).and_then(|x| x.as_str()) == Some(value),
+                        None => true,
+                    })
+                    .map(|(name, _)| name.clone())
+                    .collect();
+
+                output(0, names.join("\n"), String::new())
+            },
+            Some("create") => self.create(args),
+            Some("start") => if self.set_state(last, "running") { output(0, String::new(), String::new()) } else { not_found() },
+            Some("kill") => if self.set_state(last, "exited") { output(0, String::new(), String::new()) } else { not_found() },
+            Some("rm") => match self.script.borrow_mut().containers.remove(last) {
+                Some(_) => output(0, String::new(), String::new()),
+                None => not_found(),
+            },
+            Some("exec") => output(self.script.borrow().exec_exit_code, String::new(), String::new()),
+            _ => output(0, String::new(), String::new()),
+        }
+    }
+}
+
+impl CommandRunner for RecordingRunner {
+    fn output(&self, exe: &str, args: &[String]) -> Result<Output> {
+        self.record(exe, args);
+        Ok(self.answer(args))
+    }
+
+    fn status(&self, exe: &str, args: &[String], _quiet: bool) -> Result<ExitStatus> {
+        Ok(self.output(exe, args)?.status)
+    }
+
+    fn stream(&self, exe: &str, args: &[String]) -> Result<Box<dyn BufRead>> {
+        self.record(exe, args);
+
+        let logs = match args.first().map(|x| x.as_str()) {
+            Some("logs") => self.script.borrow().logs.get(args.last().unwrap()).cloned().unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        Ok(Box::new(Cursor::new(logs.into_bytes())))
+    }
+}
@@ -0,0 +1,108 @@
+//! Podman backend using the podman executable
+
+use super::cli::{CliRunner, CommandRunner};
+use super::{ContainerBackend, ContainerInfo, CreateOptions, ExecOptions};
+use crate::manager::ContainerManager;
+use crate::{Context, Result};
+use std::io::BufRead;
+use std::path::Path;
+use std::rc::Rc;
+
+pub struct Podman {
+    cli: CliRunner,
+}
+
+impl Podman {
+    pub fn new(dry_run: bool, runner: Rc<dyn CommandRunner>) -> Self {
+        Self {
+            cli: CliRunner {
+                exe: ContainerManager::Podman.get_executable_name(),
+                dry_run,
+                runner,
+            },
+        }
+    }
+
+    fn create_args(&self, opts: &CreateOptions) -> Result<Vec<String>> {
+        let mut cmd = self.cli.create_args(opts);
+
+        cmd.extend([
+           "--ulimit".into(), "host".into(),
+           "--annotation".into(), "run.oci.keep_original_groups=1".into(),
+        ]);
+
+        if opts.init {
+            cmd.push("--systemd=always".into());
+        }
+
+        if !opts.root {
+            cmd.extend([
+               "--userns".into(), "keep-id".into(),
+            ]);
+        }
+
+        cmd.extend(opts.extra_args.iter().cloned());
+
+        // podman takes the whole entrypoint as JSON array
+        if !opts.entrypoint.is_empty() {
+            cmd.extend([
+                "--entrypoint".into(),
+                serde_json::to_string(&opts.entrypoint).context("failed to serialize entrypoint")?,
+            ]);
+        }
+
+        cmd.push(opts.image.clone());
+
+        Ok(cmd)
+    }
+}
+
+impl ContainerBackend for Podman {
+    fn manager(&self) -> ContainerManager {
+        ContainerManager::Podman
+    }
+
+    fn create(&self, opts: &CreateOptions) -> Result<()> {
+        self.cli.run(&self.create_args(opts)?, "container creation failed")
+    }
+
+    fn start(&self, container_name: &str) -> Result<()> {
+        self.cli.start(container_name)
+    }
+
+    fn stop(&self, container_name: &str, signal: &str) -> Result<()> {
+        self.cli.stop(container_name, signal)
+    }
+
+    fn kill(&self, container_name: &str) -> Result<()> {
+        self.cli.kill(container_name)
+    }
+
+    fn exec(&self, opts: &ExecOptions) -> Result<i32> {
+        self.cli.exec(opts)
+    }
+
+    fn inspect(&self, container_name: &str) -> Result<Option<ContainerInfo>> {
+        self.cli.inspect(container_name)
+    }
+
+    fn list(&self, label: &str) -> Result<Vec<String>> {
+        self.cli.list(label)
+    }
+
+    fn cp(&self, source: &Path, container_name: &str, destination: &str) -> Result<()> {
+        self.cli.cp(source, container_name, destination)
+    }
+
+    fn cp_from(&self, container_name: &str, source: &str, destination: &Path) -> Result<()> {
+        self.cli.cp_from(container_name, source, destination)
+    }
+
+    fn remove(&self, container_name: &str) -> Result<()> {
+        self.cli.remove(container_name)
+    }
+
+    fn logs(&self, container_name: &str, follow: bool) -> Result<Box<dyn BufRead>> {
+        self.cli.logs(container_name, follow)
+    }
+}
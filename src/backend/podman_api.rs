@@ -0,0 +1,261 @@
+//! Podman backend using the libpod REST API over unix socket, falls back to the podman executable
+//! for things the API cannot express like extra arguments
+
+#[cfg(test)]
+mod tests;
+
+use super::api::{check_pull_response, resolve_env, ApiClient};
+use super::http::encode_query;
+use super::{ContainerBackend, ContainerInfo, CreateOptions, ExecOptions, Podman};
+use crate::manager::ContainerManager;
+use crate::{Context, Result};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// Prefix of all libpod API endpoints
+const API_PREFIX: &str = "/v4.0.0/libpod";
+
+/// Returns path to the podman API socket, honors CONTAINER_HOST like podman remote does
+pub fn get_socket_path() -> Option<PathBuf> {
+    if let Ok(host) = std::env::var("CONTAINER_HOST") {
+        return host.strip_prefix("unix://").map(PathBuf::from);
+    }
+
+    // SAFETY: getuid cannot fail
+    if unsafe { libc::getuid() } == 0 {
+        return Some(PathBuf::from("/run/podman/podman.sock"));
+    }
+
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|x| PathBuf::from(x).join("podman/podman.sock"))
+}
+
+/// Converts signal name like 'SIGRTMIN+3' into its number as understood by podman
+fn signal_number(name: &str) -> Option<i32> {
+    let name = name.strip_prefix("SIG").unwrap_or(name);
+
+    // podman uses the glibc value of SIGRTMIN
+    if let Some(offset) = name.strip_prefix("RTMIN") {
+        let offset = if offset.is_empty() { 0 } else { offset.strip_prefix('+')?.parse::<i32>().ok()? };
+        return Some(34 + offset);
+    }
+
+    Some(match name {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "TERM" => libc::SIGTERM,
+        "PWR" => libc::SIGPWR,
+        x => x.parse::<i32>().ok()?,
+    })
+}
+
+/// Parses '--mount' format (eg. 'type=tmpfs,destination=/tmp') into libpod mount
+fn parse_mount(mount: &str) -> serde_json::Value {
+    let mut kind = "bind";
+    let mut source = "";
+    let mut destination = "";
+    let mut options: Vec<&str> = vec![];
+
+    for option in mount.split(',') {
+        match option.split_once('=') {
+            Some(("type", x)) => kind = x,
+            Some(("source" | "src", x)) => source = x,
+            Some(("destination" | "dst" | "target", x)) => destination = x,
+            _ => options.push(option),
+        }
+    }
+
+    if kind == "tmpfs" && source.is_empty() {
+        source = "tmpfs";
+    }
+
+    json!({
+        "type": kind,
+        "source": source,
+        "destination": destination,
+        "options": options,
+    })
+}
+
+/// Parses '--volume' format (eg. '/a:/b:ro') into libpod bind mount
+fn parse_volume(volume: &str) -> serde_json::Value {
+    let mut parts = volume.splitn(3, ':');
+    let source = parts.next().unwrap_or_default();
+    let destination = parts.next().unwrap_or(source);
+    let options: Vec<&str> = parts.next().map(|x| x.split(',').collect()).unwrap_or_default();
+
+    json!({
+        "type": "bind",
+        "source": source,
+        "destination": destination,
+        "options": options,
+    })
+}
+
+/// Returns limits of this process in libpod format, same as '--ulimit host'
+fn get_host_rlimits() -> Vec<serde_json::Value> {
+    let mut limits = vec![];
+
+    for (name, resource) in [
+        ("RLIMIT_NOFILE", libc::RLIMIT_NOFILE),
+        ("RLIMIT_NPROC", libc::RLIMIT_NPROC),
+    ] {
+        let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+
+        // SAFETY: getrlimit only writes into the rlimit struct
+        if unsafe { libc::getrlimit(resource, &mut limit) } == 0 {
+            limits.push(json!({
+                "type": name,
+                "soft": limit.rlim_cur,
+                "hard": limit.rlim_max,
+            }));
+        }
+    }
+
+    limits
+}
+
+pub struct PodmanApi {
+    api: ApiClient,
+
+    /// Used for things the API cannot do
+    cli: Podman,
+}
+
+impl PodmanApi {
+    /// Returns the backend if the API is reachable through the socket
+    pub fn connect(socket: &Path, cli: Podman) -> Result<Self, Podman> {
+        let api = ApiClient::new(socket, API_PREFIX);
+        if socket.exists() && api.ping() {
+            Ok(Self { api, cli })
+        } else {
+            Err(cli)
+        }
+    }
+
+    fn create_spec(&self, opts: &CreateOptions) -> Result<serde_json::Value> {
+        let mut mounts: Vec<serde_json::Value> = opts.mounts.iter().map(|x| parse_mount(x)).collect();
+        mounts.extend(opts.volumes.iter().map(|x| parse_volume(x)));
+
+        let labels: HashMap<&str, &str> = opts.labels.iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+
+        let env: HashMap<String, String> = resolve_env(&opts.env).into_iter().collect();
+
+        let mut spec = json!({
+            "name": opts.name,
+            "image": opts.image,
+            "hostname": opts.hostname,
+            "labels": labels,
+            "env": env,
+            "mounts": mounts,
+            "entrypoint": opts.entrypoint,
+            "user": "root:root",
+            "netns": { "nsmode": "host" },
+            "selinux_opts": ["disable"],
+            "apparmor_profile": "unconfined",
+            "annotations": { "run.oci.keep_original_groups": "1" },
+            "r_limits": get_host_rlimits(),
+        });
+
+        if let Some(signal) = &opts.stop_signal {
+            spec["stop_signal"] = signal_number(signal)
+                .with_context(|| format!("unknown signal '{}'", signal))?
+                .into();
+        }
+
+        if opts.init {
+            spec["systemd"] = "always".into();
+        }
+
+        if !opts.root {
+            spec["userns"] = json!({ "nsmode": "keep-id" });
+        }
+
+        Ok(spec)
+    }
+}
+
+impl ContainerBackend for PodmanApi {
+    fn manager(&self) -> ContainerManager {
+        ContainerManager::Podman
+    }
+
+    fn create(&self, opts: &CreateOptions) -> Result<()> {
+        // extra arguments can only be understood by the podman executable
+        if !opts.extra_args.is_empty() {
+            return self.cli.create(opts);
+        }
+
+        let spec = self.create_spec(opts)?;
+        let path = self.api.path("/containers/create");
+
+        let mut response = self.api.http.request("POST", &path, Some(&spec))?;
+
+        // image does not exist locally, pull it like podman create does
+        if response.status == 404 {
+            let pull = self.api.http.request("POST", &self.api.path(&format!("/images/pull?reference={}", encode_query(&opts.image))), None)?;
+            check_pull_response(&opts.image, &pull)?;
+
+            response = self.api.http.request("POST", &path, Some(&spec))?;
+        }
+
+        if !response.is_success() {
+            return Err(response.error("container creation failed"));
+        }
+
+        Ok(())
+    }
+
+    fn start(&self, container_name: &str) -> Result<()> {
+        self.api.start(container_name)
+    }
+
+    fn stop(&self, container_name: &str, signal: &str) -> Result<()> {
+        self.api.kill(container_name, signal)
+    }
+
+    fn kill(&self, container_name: &str) -> Result<()> {
+        self.api.kill(container_name, "SIGKILL")
+    }
+
+    fn exec(&self, opts: &ExecOptions) -> Result<i32> {
+        // extra arguments can only be understood by the podman executable
+        if !opts.extra_args.is_empty() {
+            return self.cli.exec(opts);
+        }
+
+        self.api.exec(opts)
+    }
+
+    fn inspect(&self, container_name: &str) -> Result<Option<ContainerInfo>> {
+        self.api.inspect(container_name)
+    }
+
+    fn list(&self, label: &str) -> Result<Vec<String>> {
+        self.api.list(label)
+    }
+
+    fn cp(&self, source: &Path, container_name: &str, destination: &str) -> Result<()> {
+        self.api.put_archive(source, container_name, destination)
+    }
+
+    fn cp_from(&self, container_name: &str, source: &str, destination: &Path) -> Result<()> {
+        self.api.get_archive(container_name, source, destination)
+    }
+
+    fn remove(&self, container_name: &str) -> Result<()> {
+        self.api.remove(container_name)
+    }
+
+    fn logs(&self, container_name: &str, follow: bool) -> Result<Box<dyn BufRead>> {
+        self.api.logs(container_name, follow)
+    }
+}
@@ -0,0 +1,166 @@
+//! Conversion into the libpod format and the API backend talking to a stand-in server
+
+use super::*;
+use crate::backend::cli::SystemRunner;
+use crate::backend::stand_in::StandIn;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+fn podman(stand_in: &StandIn) -> PodmanApi {
+    let cli = Podman::new(false, Rc::new(SystemRunner));
+    PodmanApi::connect(&stand_in.socket(), cli)
+        .unwrap_or_else(|_| panic!("stand-in did not answer ping"))
+}
+
+#[test]
+fn signal_numbers() {
+    assert_eq!(signal_number("SIGRTMIN+3"), Some(37));
+    assert_eq!(signal_number("RTMIN+3"), Some(37));
+    assert_eq!(signal_number("SIGRTMIN"), Some(34));
+    assert_eq!(signal_number("SIGTERM"), Some(libc::SIGTERM));
+    assert_eq!(signal_number("KILL"), Some(libc::SIGKILL));
+    assert_eq!(signal_number("15"), Some(15));
+
+    for name in ["SIGRTMIN3", "SIGRTMIN+x", "SIGFOO", ""] {
+        assert_eq!(signal_number(name), None, "{:?}", name);
+    }
+}
+
+#[test]
+fn mounts() {
+    assert_eq!(parse_mount("type=tmpfs,destination=/tmp,tmpfs-size=64m"), json!({
+        "type": "tmpfs",
+        "source": "tmpfs",
+        "destination": "/tmp",
+        "options": ["tmpfs-size=64m"],
+    }));
+
+    assert_eq!(parse_mount("src=/a,target=/b,ro"), json!({
+        "type": "bind",
+        "source": "/a",
+        "destination": "/b",
+        "options": ["ro"],
+    }));
+}
+
+#[test]
+fn volumes() {
+    assert_eq!(parse_volume("/a:/b:ro,rslave"), json!({
+        "type": "bind",
+        "source": "/a",
+        "destination": "/b",
+        "options": ["ro", "rslave"],
+    }));
+
+    // destination defaults to the source
+    assert_eq!(parse_volume("/a"), json!({
+        "type": "bind",
+        "source": "/a",
+        "destination": "/a",
+        "options": [],
+    }));
+}
+
+#[test]
+fn host_rlimits() {
+    let limits = get_host_rlimits();
+
+    let mut nofile: libc::rlimit = unsafe { std::mem::zeroed() };
+
+    // SAFETY: getrlimit only writes into the rlimit struct
+    assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut nofile) }, 0);
+
+    assert_eq!(limits.len(), 2);
+    assert_eq!(limits[0], json!({ "type": "RLIMIT_NOFILE", "soft": nofile.rlim_cur, "hard": nofile.rlim_max }));
+    assert_eq!(limits[1]["type"], "RLIMIT_NPROC");
+}
+
+#[test]
+fn create() {
+    let stand_in = StandIn::new("podman-create", Box::new(|_| (201, b"{\"Id\":\"abc\"}".to_vec())));
+
+    podman(&stand_in).create(&CreateOptions {
+        name: "lm-test".into(),
+        image: "alpine".into(),
+        hostname: "pet".into(),
+        init: true,
+        env: vec!["A=b".into()],
+        labels: vec![("manager".into(), "legumemanager".into())],
+        volumes: vec!["/:/run/host:rslave".into()],
+        mounts: vec!["type=tmpfs,destination=/tmp".into()],
+        entrypoint: vec!["/lm".into(), "init".into()],
+        stop_signal: Some("SIGRTMIN+3".into()),
+        ..Default::default()
+    }).unwrap();
+
+    let requests = stand_in.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].path, "/v4.0.0/libpod/containers/create");
+
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["name"], "lm-test");
+    assert_eq!(body["image"], "alpine");
+    assert_eq!(body["hostname"], "pet");
+    assert_eq!(body["env"], json!({ "A": "b" }));
+    assert_eq!(body["labels"]["manager"], "legumemanager");
+    assert_eq!(body["entrypoint"], json!(["/lm", "init"]));
+    assert_eq!(body["stop_signal"], 37);
+    assert_eq!(body["systemd"], "always");
+    assert_eq!(body["userns"], json!({ "nsmode": "keep-id" }));
+    assert_eq!(body["netns"], json!({ "nsmode": "host" }));
+    assert_eq!(body["mounts"], json!([
+        { "type": "tmpfs", "source": "tmpfs", "destination": "/tmp", "options": [] },
+        { "type": "bind", "source": "/", "destination": "/run/host", "options": ["rslave"] },
+    ]));
+}
+
+#[test]
+fn create_pulls_missing_image() {
+    let pulled = AtomicBool::new(false);
+    let stand_in = StandIn::new("podman-pull", Box::new(move |request| {
+        if request.path.contains("/images/pull") {
+            pulled.store(true, Ordering::Relaxed);
+            (200, b"{\"stream\":\"pulling\"}\n{\"images\":[\"abc\"]}\n".to_vec())
+        } else if pulled.load(Ordering::Relaxed) {
+            (201, b"{\"Id\":\"abc\"}".to_vec())
+        } else {
+            (404, b"{\"message\":\"no such image\"}".to_vec())
+        }
+    }));
+
+    podman(&stand_in).create(&CreateOptions {
+        name: "lm-test".into(),
+        image: "docker.io/library/alpine".into(),
+        ..Default::default()
+    }).unwrap();
+
+    let paths: Vec<String> = stand_in.requests().into_iter().map(|x| x.path).collect();
+    assert_eq!(paths, [
+        "/v4.0.0/libpod/containers/create",
+        "/v4.0.0/libpod/images/pull?reference=docker.io%2Flibrary%2Falpine",
+        "/v4.0.0/libpod/containers/create",
+    ]);
+}
+
+#[test]
+fn inspect() {
+    let stand_in = StandIn::new("podman-inspect", Box::new(|request| match request.path.as_str() {
+        "/v4.0.0/libpod/containers/lm-test/json" => (200, json!({
+            "Id": "abc",
+            "Name": "lm-test",
+            "State": { "Status": "configured" },
+            "Config": { "Labels": { "manager": "legumemanager" }, "StopSignal": 37 },
+        }).to_string().into_bytes()),
+        _ => (404, b"{\"message\":\"no such container\"}".to_vec()),
+    }));
+
+    let backend = podman(&stand_in);
+
+    let info = backend.inspect("lm-test").unwrap().unwrap();
+    assert_eq!(info.name, "lm-test");
+    assert_eq!(info.state.status, "created");
+    assert_eq!(info.label("manager"), Some("legumemanager"));
+
+    assert!(backend.inspect("lm-missing").unwrap().is_none());
+}
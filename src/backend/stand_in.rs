@@ -0,0 +1,113 @@
+//! HTTP stand-in for the container manager APIs on a local unix socket, requests are answered by
+//! the test and recorded so they can be checked afterwards
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Request received by the stand-in
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+pub type Handler = dyn Fn(&Request) -> (u16, Vec<u8>) + Send + Sync;
+
+/// API stand-in answering requests using the handler, ping is always answered
+pub struct StandIn {
+    /// Directory with the socket, tests can use it for their files, it is deleted on drop
+    pub dir: PathBuf,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl StandIn {
+    pub fn new(name: &str, handler: Box<Handler>) -> Self {
+        let dir = std::env::temp_dir().join(format!("lm-api-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let listener = UnixListener::bind(dir.join("api.sock")).unwrap();
+        let requests: Arc<Mutex<Vec<Request>>> = Arc::default();
+
+        {
+            let requests = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().filter_map(|x| x.ok()) {
+                    let Some(request) = read_request(&stream) else {
+                        continue;
+                    };
+
+                    let (status, body) = if request.path.ends_with("/_ping") {
+                        (200, b"OK".to_vec())
+                    } else {
+                        handler(&request)
+                    };
+
+                    requests.lock().unwrap().push(request);
+                    respond(stream, status, &body);
+                }
+            });
+        }
+
+        Self { dir, requests }
+    }
+
+    pub fn socket(&self) -> PathBuf {
+        self.dir.join("api.sock")
+    }
+
+    /// Returns requests received so far except for ping
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().iter()
+            .filter(|x| !x.path.ends_with("/_ping"))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for StandIn {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn read_request(stream: &UnixStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((key, value)) = line.split_once(':') {
+            if key.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok()?;
+            }
+        }
+    }
+
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(Request { method, path, body })
+}
+
+fn respond(mut stream: UnixStream, status: u16, body: &[u8]) {
+    let head = format!("HTTP/1.1 {} Stand-in\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(body);
+}
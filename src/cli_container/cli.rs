@@ -1,6 +1,6 @@
 //! This file contains cli interface for use in container only
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 pub use crate::manager::ContainerManager;
 
@@ -24,6 +24,28 @@ pub struct Cli {
 #[derive(Subcommand, Debug)]
 pub enum CliCommands {
     /// Entrypoint for the container, finish setup of the container
-    Init,
+    Init(CmdInitArgs),
+
+    /// Execute command on the host, requires host daemon running on the host
+    #[command(arg_required_else_help = true)]
+    HostExec(CmdHostExecArgs),
+
+    /// Called by shell hooks when a command is not found, executes it on the host if allowed
+    #[command(hide = true, arg_required_else_help = true)]
+    CommandNotFound(CmdHostExecArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdInitArgs {
+    /// Print mounts init would do without changing anything
+    #[arg(long, visible_alias = "dry-run")]
+    pub plan: bool,
 }
 
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdHostExecArgs {
+    /// Command to execute
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub command: Vec<String>,
+}
@@ -1,4 +1,8 @@
 mod init;
+mod host_exec;
+mod command_not_found;
 
 pub use init::cmd_init;
+pub use host_exec::{cmd_host_exec, host_exec, multicall_command};
+pub use command_not_found::cmd_command_not_found;
 
@@ -0,0 +1,44 @@
+//! Command not found command, shell hooks call it to execute missing commands on the host
+
+use super::super::cli::{Cli, CmdHostExecArgs};
+use super::host_exec::host_exec;
+use crate::command_fallback::{CommandFallback, COMMAND_FALLBACK_FILE};
+use crate::Result;
+use std::path::Path;
+use std::process::exit;
+
+/// Exit code used by shells when the command was not found
+const NOT_FOUND_EXIT_CODE: i32 = 127;
+
+/// Loads fallback rules of the container, nothing is forwarded without them
+pub fn load_rules() -> CommandFallback {
+    let path = Path::new(COMMAND_FALLBACK_FILE);
+    if !path.exists() {
+        return CommandFallback::default();
+    }
+
+    CommandFallback::load(path).unwrap_or_else(|err| {
+        eprintln!("WARNING: {:#}, not executing commands on the host", err);
+        CommandFallback::default()
+    })
+}
+
+pub fn cmd_command_not_found(_args: &Cli, cmd_args: CmdHostExecArgs) -> Result<()> {
+    // command can be empty when called with only '--'
+    let Some(name) = cmd_args.command.first() else {
+        exit(NOT_FOUND_EXIT_CODE);
+    };
+
+    if !load_rules().is_allowed(name) {
+        eprintln!("{}: command not found", name);
+        exit(NOT_FOUND_EXIT_CODE);
+    }
+
+    match host_exec(&cmd_args.command) {
+        Ok(rc) => exit(rc),
+        Err(err) => {
+            eprintln!("{}: command not found, executing it on the host failed: {:#}", name, err);
+            exit(NOT_FOUND_EXIT_CODE);
+        },
+    }
+}
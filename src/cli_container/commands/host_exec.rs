@@ -1,27 +1,188 @@
-//! Host exec command
+//! Host exec command, executes command on the host through the host daemon or the Flatpak
+//! Development interface on the session bus
 
-use super::super::cli::{Cli, ContainerManager};
-use crate::{Result, Context};
-use std::process::{Command, exit, Termination};
+mod dbus;
+mod flatpak;
 
-// TODO this will be temporary, experiment with writing custom host exec using named pipes
-// https://stackoverflow.com/a/63719458
-pub const HOST_SPAWN_URL: &'static str = "https://github.com/1player/host-spawn/releases/download/v1.5.1/host-spawn-x86_64";
+use super::super::cli::{Cli, CmdHostExecArgs};
+use crate::env_vars;
+use crate::host_exec::{encode_size, read_frame, write_frame, FrameKind, HostExecRequest, CONTAINER_SOCKET_DIR, SOCKET_NAME};
+use crate::util::{get_window_size, RawTerminal};
+use crate::{Context, Error, Result};
+use std::io::{IsTerminal, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::exit;
+use std::time::Duration;
 
-// TODO download host-spawn on creation/first start
+/// Names the executable can be linked as to execute the command on the host directly
+pub const HOST_EXEC_LINKS: [&str; 3] = ["host-exec", "distrobox-host-exec", "xdg-open"];
 
-pub fn cmd_host_exec(args: &Cli, manager: &ContainerManager) -> Result<()> {
-    let status = Command::new("host-spawn")
-        .arg("--")
-        .args(&args.host_exec)
-        .spawn()
-        .with_context(|| format!("failed to execute command on host"))?
-        .wait()
-        .with_context(|| "failed to wait for the child to execute")?;
+/// Returns command to execute on the host if the executable was called through one of the links
+pub fn multicall_command(argv: &[String]) -> Option<Vec<String>> {
+    let name = Path::new(argv.first()?).file_name()?.to_str()?;
+    let mut args = &argv[1..];
 
-    let rc = status.code().context("failed to get child exit code")?;
+    match name {
+        "host-exec" | "distrobox-host-exec" => {
+            // host-spawn and distrobox allow separating the command
+            if args.first().is_some_and(|x| x == "--") {
+                args = &args[1..];
+            }
+
+            Some(args.to_vec())
+        },
+        // opens the file or url using host applications
+        "xdg-open" => Some([&["xdg-open".to_string()], args].concat()),
+        _ => None,
+    }
+}
+
+fn connect() -> Result<UnixStream> {
+    let path = Path::new(CONTAINER_SOCKET_DIR).join(SOCKET_NAME);
+
+    UnixStream::connect(&path).map_err(|err| Error::msg(format!(
+        "failed to connect to host daemon at {:?}: {}\nhint: start it on the host using 'lm host-daemon' or create the container using '--session-bus' if the host runs flatpak-session-helper",
+        path, err,
+    )))
+}
+
+/// Copies stdin into the connection until stdin is closed
+fn forward_stdin(mut stream: UnixStream) {
+    let mut stdin = std::io::stdin();
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let read = stdin.read(&mut buffer).unwrap_or(0);
+        if write_frame(&mut stream, FrameKind::Stdin, &buffer[..read]).is_err() || read == 0 {
+            return;
+        }
+    }
+}
+
+/// Sends new terminal size whenever it changes
+fn forward_resize(mut stream: UnixStream, mut last_size: Option<(u16, u16)>) {
+    loop {
+        std::thread::sleep(Duration::from_millis(100));
+
+        let size = get_window_size(libc::STDOUT_FILENO);
+        if let Some((rows, columns)) = size.filter(|_| size != last_size) {
+            if write_frame(&mut stream, FrameKind::Resize, &encode_size(rows, columns)).is_err() {
+                return;
+            }
+
+            last_size = size;
+        }
+    }
+}
+
+/// Executes the command using the host daemon with local stdio relayed over the connection
+fn daemon_exec(mut stream: UnixStream, command: &[String], cwd: &str) -> Result<i32> {
+    let tty = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let size = if tty { get_window_size(libc::STDOUT_FILENO) } else { None };
+
+    let request = HostExecRequest {
+        argv: command.to_vec(),
+        env: std::env::vars().collect(),
+        cwd: cwd.into(),
+        tty: if tty { Some(size.unwrap_or((24, 80))) } else { None },
+    };
+
+    write_frame(&mut stream, FrameKind::Request, &serde_json::to_vec(&request)?)
+        .with_context(|| "failed to send request to host daemon")?;
+
+    // restored when dropped, before exiting
+    let _raw_terminal = if tty { RawTerminal::new(libc::STDIN_FILENO) } else { None };
+
+    let input = stream.try_clone()?;
+    std::thread::spawn(move || forward_stdin(input));
+
+    if tty {
+        let resize = stream.try_clone()?;
+        std::thread::spawn(move || forward_resize(resize, size));
+    }
+
+    loop {
+        match read_frame(&mut stream).with_context(|| "failed to read from host daemon")? {
+            Some((FrameKind::Stdout, data)) => {
+                let mut stdout = std::io::stdout();
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+            },
+            Some((FrameKind::Stderr, data)) => {
+                let mut stderr = std::io::stderr();
+                stderr.write_all(&data)?;
+                stderr.flush()?;
+            },
+            Some((FrameKind::Exit, data)) => {
+                let code: [u8; 4] = data.try_into()
+                    .map_err(|_| Error::msg("invalid exit code from host daemon"))?;
+
+                return Ok(i32::from_be_bytes(code));
+            },
+            Some(_) => {},
+            None => return Err(Error::msg("connection to host daemon was closed unexpectedly")),
+        }
+    }
+}
+
+/// Executes the command on the host with local stdio attached, returns its exit code
+pub fn host_exec(command: &[String]) -> Result<i32> {
+    if command.is_empty() {
+        return Err(Error::msg("no command to execute"));
+    }
+
+    let cwd = std::env::current_dir()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "/".into());
+
+    let session_bus = || flatpak::session_bus_path()
+        .ok_or_else(|| Error::msg("session bus of the host is not available inside the container"));
+
+    match std::env::var(env_vars::LM_HOST_EXEC_TRANSPORT).as_deref() {
+        Ok("daemon") => daemon_exec(connect()?, command, &cwd),
+        Ok("flatpak") => flatpak::host_exec(&session_bus()?, command, &cwd, flatpak::CLIENT_STDIO),
+        Ok(x) => Err(Error::msg(format!("unknown host-exec transport '{}', expected 'daemon' or 'flatpak'", x))),
+        // host daemon is preferred, session bus is only mounted when the policy allows everything
+        Err(_) => match (connect(), flatpak::session_bus_path()) {
+            (Ok(stream), _) => daemon_exec(stream, command, &cwd),
+            (Err(_), Some(bus)) => flatpak::host_exec(&bus, command, &cwd, flatpak::CLIENT_STDIO),
+            (Err(err), None) => Err(err),
+        },
+    }
+}
+
+pub fn cmd_host_exec(_args: &Cli, cmd_args: CmdHostExecArgs) -> Result<()> {
+    let rc = host_exec(&cmd_args.command)?;
 
     // exit with same exit code
     exit(rc);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn multicall_links() {
+        assert_eq!(multicall_command(&argv(&["host-exec", "flatpak", "list"])), Some(argv(&["flatpak", "list"])));
+        assert_eq!(multicall_command(&argv(&["distrobox-host-exec", "--", "ls", "--", "-a"])), Some(argv(&["ls", "--", "-a"])));
+        assert_eq!(multicall_command(&argv(&["/usr/local/bin/host-exec", "--", "ls"])), Some(argv(&["ls"])));
+        assert_eq!(multicall_command(&argv(&["/usr/bin/xdg-open", "https://example.com"])), Some(argv(&["xdg-open", "https://example.com"])));
+
+        // separator is only taken from host-exec links
+        assert_eq!(multicall_command(&argv(&["xdg-open", "--", "file"])), Some(argv(&["xdg-open", "--", "file"])));
+    }
+
+    #[test]
+    fn multicall_other_names() {
+        assert_eq!(multicall_command(&argv(&["legumemanager", "host-exec", "ls"])), None);
+        assert_eq!(multicall_command(&argv(&["/lm", "init"])), None);
+        assert_eq!(multicall_command(&argv(&["host-exec-other"])), None);
+        assert_eq!(multicall_command(&[]), None);
+    }
+}
@@ -0,0 +1,690 @@
+//! Minimal D-Bus client, only what is needed to call methods with file descriptors attached and
+//! receive signals, see the D-Bus specification for the wire format
+
+#[cfg(test)]
+mod tests;
+
+use crate::{Context, Error, Result};
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+pub const BUS_NAME: &str = "org.freedesktop.DBus";
+pub const BUS_PATH: &str = "/org/freedesktop/DBus";
+
+/// Method call does not expect a reply
+pub const FLAG_NO_REPLY_EXPECTED: u8 = 1;
+
+/// Biggest message allowed by the specification
+const MAX_MESSAGE_SIZE: usize = 1 << 27;
+
+/// Deepest nesting of containers allowed by the specification, variants nested deeper would
+/// overflow the stack while reading
+const MAX_DEPTH: usize = 64;
+
+/// Value of a single complete type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Double(f64),
+    Str(String),
+    ObjectPath(String),
+    Signature(String),
+
+    /// Index into file descriptors sent with the message
+    Fd(u32),
+
+    /// Signature of the elements is needed for empty arrays
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    /// Byte string with trailing null as used by GLib for paths
+    pub fn bytestring(value: &str) -> Self {
+        let mut bytes: Vec<Value> = value.bytes().map(Value::Byte).collect();
+        bytes.push(Value::Byte(0));
+
+        Value::Array("y".into(), bytes)
+    }
+
+    pub fn signature(&self) -> String {
+        match self {
+            Self::Byte(_) => "y".into(),
+            Self::Bool(_) => "b".into(),
+            Self::I16(_) => "n".into(),
+            Self::U16(_) => "q".into(),
+            Self::I32(_) => "i".into(),
+            Self::U32(_) => "u".into(),
+            Self::I64(_) => "x".into(),
+            Self::U64(_) => "t".into(),
+            Self::Double(_) => "d".into(),
+            Self::Str(_) => "s".into(),
+            Self::ObjectPath(_) => "o".into(),
+            Self::Signature(_) => "g".into(),
+            Self::Fd(_) => "h".into(),
+            Self::Array(element, _) => format!("a{}", element),
+            Self::Struct(fields) => format!("({})", fields.iter().map(|x| x.signature()).collect::<String>()),
+            Self::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+            Self::Variant(_) => "v".into(),
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Self::U32(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(x) | Self::ObjectPath(x) | Self::Signature(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// Alignment of a type by the first character of its signature
+fn alignment(signature: &str) -> usize {
+    match signature.as_bytes().first() {
+        Some(b'n' | b'q') => 2,
+        Some(b'b' | b'i' | b'u' | b'h' | b's' | b'o' | b'a') => 4,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 1,
+    }
+}
+
+/// Splits signature into the first complete type and the rest
+fn split_signature(signature: &str) -> Result<(&str, &str)> {
+    let bytes = signature.as_bytes();
+    let end = match bytes.first() {
+        None => return Err(Error::msg("unexpected end of signature")),
+        Some(x) if !x.is_ascii() => return Err(Error::msg("invalid signature")),
+        Some(b'a') => 1 + split_signature(&signature[1..])?.0.len(),
+        Some(open @ (b'(' | b'{')) => {
+            let close = if *open == b'(' { b')' } else { b'}' };
+            let mut rest = &signature[1..];
+            while !rest.starts_with(close as char) {
+                rest = split_signature(rest)?.1;
+            }
+
+            signature.len() - rest.len() + 1
+        },
+        Some(_) => 1,
+    };
+
+    Ok(signature.split_at(end))
+}
+
+/// Serializes values in little endian
+#[derive(Default)]
+struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, alignment: usize) {
+        self.buffer.resize(self.buffer.len().next_multiple_of(alignment), 0);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.align(4);
+        self.buffer.extend(value.to_le_bytes());
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write_u32(value.len() as u32);
+        self.buffer.extend(value.as_bytes());
+        self.buffer.push(0);
+    }
+
+    fn write_signature(&mut self, value: &str) {
+        self.buffer.push(value.len() as u8);
+        self.buffer.extend(value.as_bytes());
+        self.buffer.push(0);
+    }
+
+    fn write(&mut self, value: &Value) {
+        self.align(alignment(&value.signature()));
+
+        match value {
+            Value::Byte(x) => self.buffer.push(*x),
+            Value::Bool(x) => self.write_u32(*x as u32),
+            Value::I16(x) => self.buffer.extend(x.to_le_bytes()),
+            Value::U16(x) => self.buffer.extend(x.to_le_bytes()),
+            Value::I32(x) => self.buffer.extend(x.to_le_bytes()),
+            Value::U32(x) | Value::Fd(x) => self.write_u32(*x),
+            Value::I64(x) => self.buffer.extend(x.to_le_bytes()),
+            Value::U64(x) => self.buffer.extend(x.to_le_bytes()),
+            Value::Double(x) => self.buffer.extend(x.to_le_bytes()),
+            Value::Str(x) | Value::ObjectPath(x) => self.write_str(x),
+            Value::Signature(x) => self.write_signature(x),
+            Value::Array(element, values) => {
+                self.write_u32(0);
+                let length_at = self.buffer.len() - 4;
+
+                // padding before the first element is not part of the length
+                self.align(alignment(element));
+                let start = self.buffer.len();
+
+                for i in values {
+                    self.write(i);
+                }
+
+                let length = (self.buffer.len() - start) as u32;
+                self.buffer[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+            },
+            Value::Struct(fields) => {
+                for i in fields {
+                    self.write(i);
+                }
+            },
+            Value::DictEntry(key, value) => {
+                self.write(key);
+                self.write(value);
+            },
+            Value::Variant(value) => {
+                self.write_signature(&value.signature());
+                self.write(value);
+            },
+        }
+    }
+}
+
+/// Deserializes values in little endian
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0, depth: 0 }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        self.position = self.position.next_multiple_of(alignment);
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + size)
+            .ok_or_else(|| Error::msg("unexpected end of message"))?;
+        self.position += size;
+
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        self.align(N);
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    fn read_string(&mut self, size: usize) -> Result<String> {
+        let bytes = self.take(size + 1)?;
+        String::from_utf8(bytes[..size].to_vec())
+            .with_context(|| "invalid string in message")
+    }
+
+    /// Reads a single complete type
+    fn read(&mut self, signature: &str) -> Result<Value> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::msg("values in message are nested too deep"));
+        }
+
+        self.depth += 1;
+        let value = self.read_value(signature);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn read_value(&mut self, signature: &str) -> Result<Value> {
+        let first = *signature.as_bytes().first()
+            .ok_or_else(|| Error::msg("unexpected end of signature"))?;
+
+        Ok(match first {
+            b'y' => Value::Byte(self.take(1)?[0]),
+            b'b' => Value::Bool(self.read_u32()? != 0),
+            b'n' => Value::I16(i16::from_le_bytes(self.take_array()?)),
+            b'q' => Value::U16(u16::from_le_bytes(self.take_array()?)),
+            b'i' => Value::I32(i32::from_le_bytes(self.take_array()?)),
+            b'u' => Value::U32(self.read_u32()?),
+            b'h' => Value::Fd(self.read_u32()?),
+            b'x' => Value::I64(i64::from_le_bytes(self.take_array()?)),
+            b't' => Value::U64(u64::from_le_bytes(self.take_array()?)),
+            b'd' => Value::Double(f64::from_le_bytes(self.take_array()?)),
+            b's' => {
+                let size = self.read_u32()? as usize;
+                Value::Str(self.read_string(size)?)
+            },
+            b'o' => {
+                let size = self.read_u32()? as usize;
+                Value::ObjectPath(self.read_string(size)?)
+            },
+            b'g' => {
+                let size = self.take(1)?[0] as usize;
+                Value::Signature(self.read_string(size)?)
+            },
+            b'v' => {
+                let size = self.take(1)?[0] as usize;
+                let signature = self.read_string(size)?;
+
+                // signature comes from the message so it has to be checked to be a single type
+                match split_signature(&signature)? {
+                    (single, "") => Value::Variant(Box::new(self.read(single)?)),
+                    _ => return Err(Error::msg(format!("invalid variant signature '{}' in message", signature))),
+                }
+            },
+            b'a' => {
+                let (element, "") = split_signature(&signature[1..])? else {
+                    return Err(Error::msg(format!("invalid signature '{}' in message", signature)));
+                };
+
+                let size = self.read_u32()? as usize;
+                self.align(alignment(element));
+
+                let end = self.position + size;
+                let mut values = vec![];
+                while self.position < end {
+                    values.push(self.read(element)?);
+                }
+
+                Value::Array(element.into(), values)
+            },
+            open @ (b'(' | b'{') => {
+                self.align(8);
+
+                let close = if open == b'(' { ')' } else { '}' };
+                let mut rest = signature[1..].strip_suffix(close)
+                    .filter(|x| !x.is_empty())
+                    .ok_or_else(|| Error::msg(format!("invalid signature '{}' in message", signature)))?;
+
+                let mut fields = vec![];
+                while !rest.is_empty() {
+                    let (field, next) = split_signature(rest)?;
+                    fields.push(self.read(field)?);
+                    rest = next;
+                }
+
+                if open == b'(' {
+                    Value::Struct(fields)
+                } else {
+                    let [key, value]: [Value; 2] = fields.try_into()
+                        .map_err(|_| Error::msg("invalid dict entry in message"))?;
+                    Value::DictEntry(Box::new(key), Box::new(value))
+                }
+            },
+            x => return Err(Error::msg(format!("unsupported type '{}' in message", x as char))),
+        })
+    }
+
+    /// Reads all values in the signature
+    fn read_all(&mut self, mut signature: &str) -> Result<Vec<Value>> {
+        let mut values = vec![];
+        while !signature.is_empty() {
+            let (value, rest) = split_signature(signature)?;
+            values.push(self.read(value)?);
+            signature = rest;
+        }
+
+        Ok(values)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageKind {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+impl MessageKind {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::MethodCall => 1,
+            Self::MethodReturn => 2,
+            Self::Error => 3,
+            Self::Signal => 4,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::MethodCall),
+            2 => Some(Self::MethodReturn),
+            3 => Some(Self::Error),
+            4 => Some(Self::Signal),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub kind: MessageKind,
+    pub flags: u8,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    pub fn method_call(destination: &str, path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Self {
+            kind: MessageKind::MethodCall,
+            flags: 0,
+            path: Some(path.into()),
+            interface: Some(interface.into()),
+            member: Some(member.into()),
+            error_name: None,
+            reply_serial: None,
+            destination: Some(destination.into()),
+            sender: None,
+            body,
+        }
+    }
+
+    /// Returns true if the message is the signal from the interface
+    pub fn is_signal(&self, interface: &str, member: &str) -> bool {
+        self.kind == MessageKind::Signal
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    fn encode(&self, serial: u32, fd_count: usize) -> Vec<u8> {
+        let mut body = Writer::default();
+        for i in &self.body {
+            body.write(i);
+        }
+
+        let signature: String = self.body.iter().map(|x| x.signature()).collect();
+
+        let mut fields = vec![];
+        let mut field = |code: u8, value: Value| fields.push(Value::Struct(vec![Value::Byte(code), Value::Variant(Box::new(value))]));
+
+        if let Some(x) = &self.path { field(1, Value::ObjectPath(x.clone())); }
+        if let Some(x) = &self.interface { field(2, Value::Str(x.clone())); }
+        if let Some(x) = &self.member { field(3, Value::Str(x.clone())); }
+        if let Some(x) = &self.error_name { field(4, Value::Str(x.clone())); }
+        if let Some(x) = self.reply_serial { field(5, Value::U32(x)); }
+        if let Some(x) = &self.destination { field(6, Value::Str(x.clone())); }
+        if !signature.is_empty() { field(8, Value::Signature(signature)); }
+        if fd_count > 0 { field(9, Value::U32(fd_count as u32)); }
+
+        let mut message = Writer::default();
+        message.buffer.extend([b'l', self.kind.to_u8(), self.flags, 1]);
+        message.write_u32(body.buffer.len() as u32);
+        message.write_u32(serial);
+        message.write(&Value::Array("(yv)".into(), fields));
+        message.align(8);
+        message.buffer.extend(body.buffer);
+
+        message.buffer
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        if data.first() != Some(&b'l') {
+            return Err(Error::msg("only little endian messages are supported"));
+        }
+
+        let mut reader = Reader::new(data);
+        let header = reader.take(4)?;
+        let kind = MessageKind::from_u8(header[1])
+            .ok_or_else(|| Error::msg(format!("invalid message type {}", header[1])))?;
+        let body_size = reader.read_u32()? as usize;
+
+        // serial of received messages is not needed
+        reader.read_u32()?;
+
+        let mut message = Self {
+            kind,
+            flags: header[2],
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body: vec![],
+        };
+
+        let mut signature = String::new();
+
+        let Value::Array(_, fields) = reader.read("a(yv)")? else { unreachable!() };
+        for i in fields {
+            let Value::Struct(i) = i else { unreachable!() };
+            let (Value::Byte(code), Value::Variant(value)) = (&i[0], &i[1]) else { unreachable!() };
+
+            let text = value.as_str().map(|x| x.to_string());
+            match code {
+                1 => message.path = text,
+                2 => message.interface = text,
+                3 => message.member = text,
+                4 => message.error_name = text,
+                5 => message.reply_serial = value.as_u32(),
+                6 => message.destination = text,
+                7 => message.sender = text,
+                8 => signature = text.unwrap_or_default(),
+                _ => {},
+            }
+        }
+
+        reader.align(8);
+        let body = reader.take(body_size)?;
+        message.body = Reader::new(body).read_all(&signature)?;
+
+        Ok(message)
+    }
+}
+
+/// Returns path of the socket from address like 'unix:path=/run/user/1000/bus,guid=...', only
+/// unix sockets with a path are supported
+pub fn parse_address(address: &str) -> Option<PathBuf> {
+    address.split(';')
+        .filter_map(|x| x.strip_prefix("unix:"))
+        .flat_map(|x| x.split(','))
+        .find_map(|x| x.strip_prefix("path="))
+        .map(PathBuf::from)
+}
+
+/// Sends the data with file descriptors attached
+fn send_with_fds(stream: &UnixStream, data: &[u8], fds: &[RawFd]) -> std::io::Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+
+    let fds_size = std::mem::size_of_val(fds) as u32;
+
+    // SAFETY: CMSG_SPACE only calculates the size
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_size) } as usize];
+
+    // SAFETY: zeroed msghdr is valid, all pointers point to buffers that outlive the call
+    let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+    header.msg_iov = &mut iov;
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    header.msg_controllen = control.len() as _;
+
+    // SAFETY: the control buffer is big enough for a single message with all fds
+    let sent = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&header);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(fds_size) as _;
+        std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, fds.len());
+
+        libc::sendmsg(stream.as_raw_fd(), &header, libc::MSG_NOSIGNAL)
+    };
+
+    if sent < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(sent as usize)
+}
+
+/// Connection to a bus, messages can be sent from multiple threads but should be received from
+/// one only
+pub struct Connection {
+    stream: UnixStream,
+    write_lock: Mutex<()>,
+    serial: AtomicU32,
+}
+
+impl Connection {
+    /// Connects and authenticates using credentials of the socket, the unique name is requested
+    /// as the bus requires
+    pub fn connect(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("failed to connect to bus at {:?}", path))?;
+
+        let connection = Self {
+            stream,
+            write_lock: Mutex::new(()),
+            serial: AtomicU32::new(1),
+        };
+
+        connection.authenticate()
+            .with_context(|| format!("failed to authenticate to bus at {:?}", path))?;
+
+        connection.call(Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "Hello", vec![]), &[])?;
+
+        Ok(connection)
+    }
+
+    fn read_line(&self) -> Result<String> {
+        let mut line = vec![];
+        let mut byte = [0u8; 1];
+
+        while !line.ends_with(b"\r\n") {
+            if (&self.stream).read(&mut byte)? == 0 {
+                return Err(Error::msg("connection closed during authentication"));
+            }
+
+            line.push(byte[0]);
+        }
+
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+
+    /// Uses EXTERNAL mechanism without the uid so the bus takes it from the socket, the uid
+    /// inside the container may differ from the one on the host
+    fn authenticate(&self) -> Result<()> {
+        let mut stream = &self.stream;
+        stream.write_all(b"\0AUTH EXTERNAL\r\n")?;
+
+        let mut reply = self.read_line()?;
+        if reply == "DATA" {
+            stream.write_all(b"DATA\r\n")?;
+            reply = self.read_line()?;
+        }
+
+        if !reply.starts_with("OK ") {
+            return Err(Error::msg(format!("authentication rejected: {}", reply)));
+        }
+
+        stream.write_all(b"NEGOTIATE_UNIX_FD\r\n")?;
+        let reply = self.read_line()?;
+        if reply != "AGREE_UNIX_FD" {
+            return Err(Error::msg(format!("bus does not support passing file descriptors: {}", reply)));
+        }
+
+        stream.write_all(b"BEGIN\r\n")?;
+
+        Ok(())
+    }
+
+    /// Sends the message with file descriptors attached, returns its serial
+    pub fn send(&self, message: &Message, fds: &[RawFd]) -> Result<u32> {
+        let serial = self.serial.fetch_add(1, Ordering::Relaxed);
+        let data = message.encode(serial, fds.len());
+
+        let _lock = self.write_lock.lock().unwrap_or_else(|x| x.into_inner());
+
+        // fds are sent only with the first part of the message
+        let sent = if fds.is_empty() { 0 } else { send_with_fds(&self.stream, &data, fds)? };
+        (&self.stream).write_all(&data[sent..])
+            .with_context(|| "failed to send message to bus")?;
+
+        Ok(serial)
+    }
+
+    /// Receives next message
+    pub fn receive(&self) -> Result<Message> {
+        let mut stream = &self.stream;
+
+        // fixed part of the header and length of the header fields array
+        let mut data = vec![0u8; 16];
+        stream.read_exact(&mut data)
+            .with_context(|| "failed to receive message from bus")?;
+
+        let body_size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let fields_size = u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize;
+        let size = (16 + fields_size).next_multiple_of(8) + body_size;
+        if size > MAX_MESSAGE_SIZE {
+            return Err(Error::msg("message from bus is too big"));
+        }
+
+        data.resize(size, 0);
+        stream.read_exact(&mut data[16..])
+            .with_context(|| "failed to receive message from bus")?;
+
+        Message::decode(&data)
+    }
+
+    /// Calls the method and waits for its reply, messages received in the meantime are passed to
+    /// the callback
+    pub fn call_with(&self, message: Message, fds: &[RawFd], mut other: impl FnMut(Message)) -> Result<Message> {
+        let serial = self.send(&message, fds)?;
+
+        loop {
+            let reply = self.receive()?;
+            if reply.reply_serial != Some(serial) {
+                other(reply);
+                continue;
+            }
+
+            if reply.kind == MessageKind::Error {
+                let text = reply.body.first().and_then(|x| x.as_str()).unwrap_or_default();
+                return Err(Error::msg(format!(
+                    "{}: {}",
+                    reply.error_name.as_deref().unwrap_or("unknown error"),
+                    text,
+                )));
+            }
+
+            return Ok(reply);
+        }
+    }
+
+    /// Calls the method and waits for its reply ignoring other messages
+    pub fn call(&self, message: Message, fds: &[RawFd]) -> Result<Message> {
+        self.call_with(message, fds, |_| {})
+    }
+}
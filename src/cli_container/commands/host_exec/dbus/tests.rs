@@ -0,0 +1,403 @@
+//! Wire format checked by encoding and decoding, and the connection against a real dbus-daemon
+//! with a fake Flatpak service registered on it
+
+use super::super::flatpak;
+use super::*;
+use std::io::{BufRead, BufReader};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+fn round_trip(values: Vec<Value>) -> Vec<Value> {
+    let mut writer = Writer::default();
+    for i in &values {
+        writer.write(i);
+    }
+
+    let signature: String = values.iter().map(|x| x.signature()).collect();
+    Reader::new(&writer.buffer).read_all(&signature).unwrap()
+}
+
+#[test]
+fn values_round_trip() {
+    let values = vec![
+        Value::Byte(7),
+        Value::Bool(true),
+        Value::I16(-2),
+        Value::U16(3),
+        Value::I32(-4),
+        Value::U32(5),
+        Value::I64(-6),
+        Value::U64(7),
+        Value::Double(0.5),
+        Value::Str("text".into()),
+        Value::ObjectPath("/org/freedesktop/portal/Flatpak".into()),
+        Value::Signature("a{sv}".into()),
+        Value::Fd(1),
+        Value::bytestring("/home/user"),
+        Value::Array("s".into(), vec![]),
+        Value::Array("{sv}".into(), vec![
+            Value::DictEntry(Box::new(Value::Str("a".into())), Box::new(Value::Variant(Box::new(Value::U64(1))))),
+            Value::DictEntry(Box::new(Value::Str("b".into())), Box::new(Value::Variant(Box::new(Value::Struct(vec![Value::Byte(1), Value::Str("c".into())]))))),
+        ]),
+        Value::Struct(vec![Value::Byte(1), Value::I64(2), Value::Array("ay".into(), vec![Value::bytestring("x")])]),
+    ];
+
+    assert_eq!(round_trip(values.clone()), values);
+}
+
+#[test]
+fn message_round_trip() {
+    let mut message = Message::method_call(
+        "org.freedesktop.Flatpak",
+        "/org/freedesktop/Flatpak/Development",
+        "org.freedesktop.Flatpak.Development",
+        "HostCommand",
+        vec![Value::bytestring("/"), Value::Array("u".into(), vec![Value::U32(1), Value::U32(2)])],
+    );
+    message.flags = FLAG_NO_REPLY_EXPECTED;
+    message.reply_serial = Some(3);
+
+    let decoded = Message::decode(&message.encode(7, 2)).unwrap();
+
+    assert_eq!(decoded.kind, MessageKind::MethodCall);
+    assert_eq!(decoded.flags, FLAG_NO_REPLY_EXPECTED);
+    assert_eq!(decoded.path, message.path);
+    assert_eq!(decoded.interface, message.interface);
+    assert_eq!(decoded.member, message.member);
+    assert_eq!(decoded.destination, message.destination);
+    assert_eq!(decoded.reply_serial, Some(3));
+    assert_eq!(decoded.error_name, None);
+    assert_eq!(decoded.body, message.body);
+}
+
+#[test]
+fn invalid_signatures_are_refused() {
+    // variant signatures are read from the message itself
+    for data in [&b"\x00\x00"[..], b"\x01(\x00", b"\x02ii\x00", b"\x02()\x00", b"\x01a\x00", b"\x02\xc3\xa9\x00"] {
+        assert!(Reader::new(data).read("v").is_err(), "{:?}", data);
+    }
+
+    for signature in ["", "(", "{", "()", "(i", "a"] {
+        assert!(Reader::new(&[0; 16]).read(signature).is_err(), "{:?}", signature);
+    }
+}
+
+#[test]
+fn deeply_nested_variants_are_refused() {
+    let mut data = b"\x01v\x00".repeat(10_000);
+    data.extend(b"\x01y\x00\x01");
+
+    assert!(Reader::new(&data).read("v").is_err());
+}
+
+/// Session bus running for the duration of a test, stopped on drop
+struct TestBus {
+    dir: PathBuf,
+    daemon: Child,
+}
+
+impl TestBus {
+    fn start(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("lm-dbus-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .arg(format!("--address=unix:path={}", dir.join("bus").display()))
+            .stdout(Stdio::piped())
+            .spawn();
+
+        let mut daemon = daemon.expect("dbus-daemon is required to run this test");
+
+        // address is printed once the bus is listening
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+        assert_eq!(parse_address(address.trim()), Some(dir.join("bus")), "{:?}", address);
+
+        Self { dir, daemon }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("bus")
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn call_session_bus() {
+    let bus = TestBus::start("call");
+
+    let connection = Connection::connect(&bus.path()).unwrap();
+
+    let reply = connection.call(Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "GetId", vec![]), &[]).unwrap();
+    assert_eq!(reply.kind, MessageKind::MethodReturn);
+    assert_eq!(reply.body[0].as_str().map(|x| x.len()), Some(32));
+
+    let reply = connection.call(Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "ListNames", vec![]), &[]).unwrap();
+    let [Value::Array(element, names)] = reply.body.as_slice() else {
+        panic!("unexpected reply {:?}", reply.body);
+    };
+    assert_eq!(element, "s");
+    assert!(names.contains(&Value::Str(BUS_NAME.into())));
+
+    // errors of the bus are returned with their name
+    let err = connection.call(Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "GetNameOwner", vec![Value::Str("org.example.Missing".into())]), &[]).unwrap_err();
+    assert!(err.to_string().starts_with("org.freedesktop.DBus.Error.NameHasNoOwner"), "{}", err);
+}
+
+/// Reads exactly the size of the buffer, file descriptors attached to the data are collected
+fn receive_exact(stream: &UnixStream, mut buffer: &mut [u8], fds: &mut Vec<OwnedFd>) -> std::io::Result<()> {
+    while !buffer.is_empty() {
+        let mut iov = libc::iovec {
+            iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buffer.len(),
+        };
+        let mut control = [0u64; 32];
+
+        // SAFETY: zeroed msghdr is valid, all pointers point to buffers that outlive the call
+        let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+        header.msg_iov = &mut iov;
+        header.msg_iovlen = 1;
+        header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        header.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        // SAFETY: the header describes valid buffers
+        let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut header, libc::MSG_CMSG_CLOEXEC) };
+        match received {
+            x if x < 0 => return Err(std::io::Error::last_os_error()),
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            _ => {},
+        }
+
+        // SAFETY: the control messages were filled by the kernel, received fds are owned by us
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&header);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / std::mem::size_of::<RawFd>();
+                    let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                    for i in 0..count {
+                        fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                    }
+                }
+
+                cmsg = libc::CMSG_NXTHDR(&header, cmsg);
+            }
+        }
+
+        buffer = &mut buffer[received as usize..];
+    }
+
+    Ok(())
+}
+
+/// Receives next message with its serial and file descriptors, the client never needs either
+fn receive_with_fds(connection: &Connection) -> std::io::Result<(u32, Message, Vec<OwnedFd>)> {
+    let mut fds = vec![];
+
+    let mut data = vec![0u8; 16];
+    receive_exact(&connection.stream, &mut data, &mut fds)?;
+
+    let body_size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    let fields_size = u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize;
+    data.resize((16 + fields_size).next_multiple_of(8) + body_size, 0);
+    receive_exact(&connection.stream, &mut data[16..], &mut fds)?;
+
+    let serial = u32::from_le_bytes(data[8..12].try_into().unwrap());
+
+    Ok((serial, Message::decode(&data).unwrap(), fds))
+}
+
+fn from_bytestring(value: &Value) -> String {
+    let Value::Array(_, bytes) = value else {
+        panic!("expected byte string, got {:?}", value);
+    };
+
+    bytes.iter()
+        .filter_map(|x| match x {
+            Value::Byte(0) => None,
+            Value::Byte(x) => Some(*x as char),
+            x => panic!("expected byte, got {:?}", x),
+        })
+        .collect()
+}
+
+/// Executes the command as a child of the test, HostCommandExited is emitted once it exits
+fn host_command(connection: &Arc<Connection>, serial: u32, message: &Message, fds: Vec<OwnedFd>) {
+    let [cwd, Value::Array(_, argv), Value::Array(_, stdio), Value::Array(_, env), Value::U32(_)] = message.body.as_slice() else {
+        panic!("invalid HostCommand call {:?}", message.body);
+    };
+
+    let argv: Vec<String> = argv.iter().map(from_bytestring).collect();
+
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]).current_dir(from_bytestring(cwd));
+
+    for i in env {
+        let Value::DictEntry(key, value) = i else { unreachable!() };
+        command.env(key.as_str().unwrap(), value.as_str().unwrap());
+    }
+
+    for i in stdio {
+        let Value::DictEntry(target, index) = i else { unreachable!() };
+        let (Value::U32(target), Value::Fd(index)) = (target.as_ref(), index.as_ref()) else { unreachable!() };
+        let fd = Stdio::from(fds[*index as usize].try_clone().unwrap());
+
+        match target {
+            0 => command.stdin(fd),
+            1 => command.stdout(fd),
+            2 => command.stderr(fd),
+            x => panic!("unexpected target fd {}", x),
+        };
+    }
+
+    let mut child = command.spawn().unwrap();
+
+    // the client waits for EOF of output so no copies may be left open
+    drop(command);
+    drop(fds);
+
+    let pid = child.id();
+    connection.send(&Message {
+        kind: MessageKind::MethodReturn,
+        flags: 0,
+        path: None,
+        interface: None,
+        member: None,
+        error_name: None,
+        reply_serial: Some(serial),
+        destination: message.sender.clone(),
+        sender: None,
+        body: vec![Value::U32(pid)],
+    }, &[]).unwrap();
+
+    let connection = connection.clone();
+    std::thread::spawn(move || {
+        let status = child.wait().unwrap();
+
+        let mut signal = Message::method_call("", "/org/freedesktop/Flatpak/Development", "org.freedesktop.Flatpak.Development", "HostCommandExited", vec![
+            Value::U32(pid),
+            Value::U32(status.into_raw() as u32),
+        ]);
+        signal.kind = MessageKind::Signal;
+        signal.destination = None;
+
+        connection.send(&signal, &[]).unwrap();
+    });
+}
+
+/// Stand-in for flatpak-session-helper, it runs until the bus is stopped
+fn start_fake_flatpak(bus: &Path) -> JoinHandle<()> {
+    let connection = Arc::new(Connection::connect(bus).unwrap());
+
+    // fails the call if the name cannot be owned right away
+    let reply = connection.call(Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "RequestName", vec![
+        Value::Str("org.freedesktop.Flatpak".into()),
+        Value::U32(4),
+    ]), &[]).unwrap();
+    assert_eq!(reply.body, [Value::U32(1)]);
+
+    std::thread::spawn(move || {
+        while let Ok((serial, message, fds)) = receive_with_fds(&connection) {
+            if message.kind != MessageKind::MethodCall {
+                continue;
+            }
+
+            match (message.member.as_deref(), message.body.as_slice()) {
+                (Some("HostCommand"), _) => host_command(&connection, serial, &message, fds),
+                (Some("HostCommandSignal"), [Value::U32(pid), Value::U32(signal), Value::Bool(_)]) => {
+                    // SAFETY: sending a signal has no memory safety implications
+                    unsafe { libc::kill(*pid as libc::pid_t, *signal as libc::c_int) };
+                },
+                x => panic!("unexpected call {:?}", x),
+            }
+        }
+    })
+}
+
+/// Executes the script on the host in a thread of the name, returns the thread, its id and local
+/// ends of stdin, stdout and stderr of the script
+fn spawn_host_exec(name: &str, bus: &Path, script: &str) -> (JoinHandle<i32>, libc::pid_t, [UnixStream; 3]) {
+    let (local, remote): (Vec<UnixStream>, Vec<UnixStream>) = (0..3)
+        .map(|_| UnixStream::pair().unwrap())
+        .unzip();
+
+    let bus = bus.to_path_buf();
+    let command = vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    let thread = std::thread::Builder::new()
+        .name(name.into())
+        .spawn(move || {
+            // SAFETY: gettid has no preconditions
+            sender.send(unsafe { libc::gettid() }).unwrap();
+
+            let stdio: Vec<RawFd> = remote.iter().map(|x| x.as_raw_fd()).collect();
+            flatpak::host_exec(&bus, &command, "/", stdio.try_into().unwrap()).unwrap()
+        })
+        .unwrap();
+
+    (thread, receiver.recv().unwrap(), local.try_into().unwrap())
+}
+
+#[test]
+fn flatpak_host_exec() {
+    let bus = TestBus::start("flatpak");
+    let _service = start_fake_flatpak(&bus.path());
+
+    let (thread, _, [mut stdin, mut stdout, mut stderr]) = spawn_host_exec("lm-host-exec", &bus.path(), "cat; echo error >&2; exit 3");
+
+    stdin.write_all(b"hello\n").unwrap();
+    stdin.shutdown(std::net::Shutdown::Write).unwrap();
+
+    assert_eq!(thread.join().unwrap(), 3);
+
+    let mut output = String::new();
+    stdout.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "hello\n");
+
+    output.clear();
+    stderr.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "error\n");
+}
+
+#[test]
+fn flatpak_host_exec_forwards_signals() {
+    let bus = TestBus::start("flatpak-signals");
+    let _service = start_fake_flatpak(&bus.path());
+
+    let name = "lm-host-signal";
+    let (thread, tid, [_stdin, stdout, _stderr]) = spawn_host_exec(name, &bus.path(), "trap 'exit 7' USR1; echo ready; while :; do sleep 0.1; done");
+
+    let mut line = String::new();
+    BufReader::new(&stdout).read_line(&mut line).unwrap();
+    assert_eq!(line, "ready\n");
+
+    // the forwarding thread inherits the name, signals sent to the whole process would kill the
+    // other tests as they do not block them
+    let find_forwarding_thread = || std::fs::read_dir("/proc/self/task").unwrap()
+        .filter_map(|x| x.ok()?.file_name().to_str()?.parse::<libc::pid_t>().ok())
+        .filter(|x| *x != tid)
+        .find(|x| std::fs::read_to_string(format!("/proc/self/task/{}/comm", x)).is_ok_and(|x| x.trim() == name));
+
+    let forwarding = (0..50)
+        .find_map(|_| find_forwarding_thread().or_else(|| { std::thread::sleep(Duration::from_millis(100)); None }))
+        .expect("signal forwarding thread was not started");
+
+    // SAFETY: sending a signal has no memory safety implications, the thread blocks it
+    unsafe { libc::syscall(libc::SYS_tgkill, libc::getpid(), forwarding, libc::SIGUSR1) };
+
+    assert_eq!(thread.join().unwrap(), 7);
+}
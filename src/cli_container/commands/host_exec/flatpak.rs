@@ -0,0 +1,161 @@
+//! Host exec over the Flatpak Development interface on the session bus, it is provided by
+//! flatpak-session-helper on hosts with flatpak installed and does not need the host daemon
+
+use super::dbus::{self, Connection, Message, Value};
+use crate::host_exec::{is_ignored_env, CONTAINER_SESSION_BUS};
+use crate::{Error, Result};
+use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const FLATPAK_NAME: &str = "org.freedesktop.Flatpak";
+const DEVELOPMENT_PATH: &str = "/org/freedesktop/Flatpak/Development";
+const DEVELOPMENT_INTERFACE: &str = "org.freedesktop.Flatpak.Development";
+
+/// Command is killed when the connection to the bus is closed
+const FLAG_WATCH_BUS: u32 = 2;
+
+/// Stdio of the client passed to the command
+pub const CLIENT_STDIO: [RawFd; 3] = [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO];
+
+/// Signals sent to the client that are forwarded to the command, terminal related signals are
+/// handled by the terminal itself as it is passed to the command directly
+const FORWARDED_SIGNALS: [libc::c_int; 6] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+];
+
+/// Returns path of the session bus socket, the one mounted by legumemanager is preferred
+pub fn session_bus_path() -> Option<PathBuf> {
+    if Path::new(CONTAINER_SESSION_BUS).exists() {
+        return Some(CONTAINER_SESSION_BUS.into());
+    }
+
+    std::env::var("DBUS_SESSION_BUS_ADDRESS").ok()
+        .and_then(|x| dbus::parse_address(&x))
+        .filter(|x| x.exists())
+}
+
+fn signal_set() -> libc::sigset_t {
+    // SAFETY: sigemptyset initializes the set and sigaddset only modifies it
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for i in FORWARDED_SIGNALS {
+            libc::sigaddset(&mut set, i);
+        }
+
+        set
+    }
+}
+
+/// Sends every forwarded signal the client receives to the process group of the command
+fn forward_signals(connection: Arc<Connection>, pid: u32) {
+    let set = signal_set();
+
+    loop {
+        let mut signal = 0;
+
+        // SAFETY: the signals are blocked in all threads so they are only received here
+        if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+            return;
+        }
+
+        let mut message = Message::method_call(FLATPAK_NAME, DEVELOPMENT_PATH, DEVELOPMENT_INTERFACE, "HostCommandSignal", vec![
+            Value::U32(pid),
+            Value::U32(signal as u32),
+            Value::Bool(true),
+        ]);
+        message.flags = dbus::FLAG_NO_REPLY_EXPECTED;
+
+        if connection.send(&message, &[]).is_err() {
+            return;
+        }
+    }
+}
+
+/// Converts wait status to exit code like the shell does
+fn exit_code(status: u32) -> i32 {
+    let status = status as libc::c_int;
+
+    if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else {
+        128 + libc::WTERMSIG(status)
+    }
+}
+
+/// Executes the command on the host with the file descriptors as its stdio, returns its exit code
+pub fn host_exec(bus: &Path, command: &[String], cwd: &str, stdio: [RawFd; 3]) -> Result<i32> {
+    let connection = Arc::new(Connection::connect(bus)?);
+
+    // subscribe before starting the command so the signal cannot be missed
+    connection.call(Message::method_call(dbus::BUS_NAME, dbus::BUS_PATH, dbus::BUS_NAME, "AddMatch", vec![
+        Value::Str(format!("type='signal',sender='{}',interface='{}',member='HostCommandExited'", FLATPAK_NAME, DEVELOPMENT_INTERFACE)),
+    ]), &[])?;
+
+    let env: Vec<Value> = std::env::vars()
+        .filter(|(key, _)| !is_ignored_env(key))
+        .map(|(key, value)| Value::DictEntry(Box::new(Value::Str(key)), Box::new(Value::Str(value))))
+        .collect();
+
+    // child fd mapped to index of the fd sent with the message
+    let fds: Vec<Value> = (0..3)
+        .map(|x| Value::DictEntry(Box::new(Value::U32(x)), Box::new(Value::Fd(x))))
+        .collect();
+
+    let request = Message::method_call(FLATPAK_NAME, DEVELOPMENT_PATH, DEVELOPMENT_INTERFACE, "HostCommand", vec![
+        Value::bytestring(cwd),
+        Value::Array("ay".into(), command.iter().map(|x| Value::bytestring(x)).collect()),
+        Value::Array("{uh}".into(), fds),
+        Value::Array("{ss}".into(), env),
+        Value::U32(FLAG_WATCH_BUS),
+    ]);
+
+    // signals are received by the forwarding thread only, blocked before it is spawned so it
+    // inherits the mask
+    let set = signal_set();
+
+    // SAFETY: only changes the signal mask of the current thread
+    unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) };
+
+    // the command may exit before the reply is received
+    let mut exited: Vec<(u32, u32)> = vec![];
+    let collect_exited = |message: Message, exited: &mut Vec<(u32, u32)>| {
+        if message.is_signal(DEVELOPMENT_INTERFACE, "HostCommandExited") {
+            if let [Value::U32(pid), Value::U32(status)] = message.body[..] {
+                exited.push((pid, status));
+            }
+        }
+    };
+
+    let reply = match connection.call_with(request, &stdio, |x| collect_exited(x, &mut exited)) {
+        Ok(x) => x,
+        // same as the host daemon when the command cannot be executed
+        Err(err) => {
+            eprintln!("failed to execute '{}': {:#}", command[0], err);
+            return Ok(127);
+        },
+    };
+
+    let pid = reply.body.first()
+        .and_then(|x| x.as_u32())
+        .ok_or_else(|| Error::msg("invalid reply to HostCommand"))?;
+
+    {
+        let connection = connection.clone();
+        std::thread::spawn(move || forward_signals(connection, pid));
+    }
+
+    loop {
+        if let Some((_, status)) = exited.iter().find(|(x, _)| *x == pid) {
+            return Ok(exit_code(*status));
+        }
+
+        collect_exited(connection.receive()?, &mut exited);
+    }
+}
@@ -1,228 +1,181 @@
 //! Container init command
 
-use std::path::{Path, PathBuf};
+mod links;
+mod log;
+mod mounts;
+mod network;
+mod packages;
+mod shell_hooks;
+mod supervisor;
+mod systemd;
+mod user;
+
+use std::path::Path;
 use std::fs;
-use std::process::Command;
-use super::super::cli::{Cli, ContainerManager};
-use crate::{Context, Error, Result};
-use serde::Deserialize;
-
-fn get_locked_mount_flags(path: &str) -> Option<Vec<String>> {
-    // NOTE this may fail on older versions of findmnt
-
-    #[derive(Deserialize)]
-    struct FindMntFilesystem {
-        target: String,
-        options: String,
-    }
+use super::super::cli::{Cli, CmdInitArgs, ContainerManager};
+use log::InitLogger;
+use crate::mount_spec::{MountSpec, MOUNT_SPEC_FILE};
+use crate::{Context, Result};
+
+/// Mounts needed before host paths are mounted
+fn base_mount_actions() -> Vec<mounts::MountAction> {
+    vec![
+        mounts::MountAction::mount(Some("devpts"), "/dev/pts", Some("devpts"), libc::MS_NOEXEC | libc::MS_NOSUID, Some("newinstance,ptmxmode=0666,mode=0620")),
+        mounts::MountAction::mount(Some("/dev/pts/ptmx"), "/dev/ptmx", None, libc::MS_BIND, None),
+        mounts::MountAction::mount(None, "/", None, libc::MS_SHARED | libc::MS_REC, None),
+    ]
+}
 
-    /// Structure returned by findmnt command when using --json argument
-    #[derive(Deserialize)]
-    struct FindMntResult {
-        filesystems: Vec<FindMntFilesystem>,
+/// Loads mount spec of the container, containers without a spec use the default mounts
+fn load_mount_spec() -> MountSpec {
+    if !Path::new(MOUNT_SPEC_FILE).exists() {
+        return MountSpec::default();
     }
 
+    MountSpec::load(Path::new(MOUNT_SPEC_FILE)).unwrap_or_else(|err| {
+        eprintln!("WARNING: {:#}, using default mounts", err);
+        MountSpec::default()
+    })
+}
 
-    let output = Command::new("findmnt")
-        .args(["--json", "--target", path])
-        .output()
-        .expect("unable to execute findmnt");
+/// Prints every mount step init would do based on the current mount table
+fn print_plan() -> Result<()> {
+    let mountinfo = mounts::read_mountinfo()?;
 
-    if !output.status.success() {
-        return None;
+    for action in base_mount_actions() {
+        println!("{}", action);
     }
 
-    if let Ok(json) = serde_json::from_slice::<FindMntResult>(&output.stdout) {
-        if json.filesystems.len() > 1 {
-            panic!("findmnt output has more than one filesystem!");
-        }
-
-        let fs: &FindMntFilesystem = &json.filesystems[0];
-        if fs.target != path {
-            return get_locked_mount_flags(&fs.target);
+    for i in load_mount_spec().mounts() {
+        match mounts::plan_bind_mount(&mountinfo, &i.source(), &i.destination, i.read_only)? {
+            Some(actions) => actions.iter().for_each(|x| println!("{}", x)),
+            None => println!("skip {} (source {} does not exist)", i.destination, i.source()),
         }
+    }
 
-        let mut flags: Vec<String> = vec![];
-        for i in fs.options.split(",") {
-            if i == "nodev" || i == "noexec" || i == "nosuid" {
-                flags.push(i.into())
-            }
-        }
+    Ok(())
+}
 
-        return Some(flags);
+pub fn cmd_init(args: &Cli, _manager: &ContainerManager, cmd_args: CmdInitArgs) -> Result<()> {
+    if cmd_args.plan {
+        return print_plan();
     }
 
-    // panic as this means something broke
-    panic!("failed to parse findmnt output");
-}
+    // NOTE set on creation as reading labels from within the container is not possible
+    let use_systemd = std::env::var("manager_init").is_ok_and(|x| x == "true");
 
-fn bind_mount(source: &str, mountpoint: &str, flags: Vec<String>) -> Result<()> {
-    let mut source_path = PathBuf::from(source);
+    // fail early so the container does not run half set up
+    let systemd = if use_systemd {
+        Some(systemd::find_systemd().ok_or_else(systemd::missing_systemd_error)?)
+    } else {
+        None
+    };
 
-    // if its a link
-    if source_path.as_path().is_symlink() {
-        // do not prepend twice
-        if !source_path.as_path().starts_with("/run/host/") {
-            let symlink_target = source_path.as_path().read_link()
-                .with_context(|| format!("failed to read link {:?}", source_path))?;
-            source_path = PathBuf::from("/run/host").join(symlink_target);
-        }
+    // stop signals are queued during setup and handled by the supervisor afterwards
+    if supervisor::is_pid1() && systemd.is_none() {
+        supervisor::block_signals()?;
     }
 
-    // check if source exists
-    match source_path.as_path().try_exists() {
-        Ok(true) => {},
-        // im gonna ignore errors and return here
-        _ => return Ok(()),
-    }
+    let log = InitLogger::new(args.verbose >= 1);
+    let details = args.verbose >= 2;
 
-    let mountpoint_path = Path::new(mountpoint);
+    log.step("packages", || {
+        if let Err(err) = packages::bootstrap(args.verbose) {
+            log.warning("packages", &err);
+        }
 
-    // check if mountpoint is a symlink
-    if mountpoint_path.is_symlink() {
-        // try to remove the file
-        fs::remove_file(mountpoint_path)
-            .with_context(|| format!("failed to delete link {}", mountpoint))?;
-    } else {
-        // check if mountpoint exists
-        match mountpoint_path.try_exists() {
-            Ok(true) => {
-                // check if mountpoint is already mounted and unmount it
-                let result = Command::new("findmnt")
-                    .arg(mountpoint)
-                    .output()
-                    .with_context(|| "failed to execute findmnt command")?;
-
-                // mountpoint is mounted
-                if result.status.success() {
-                    // unmount it
-                    let result = Command::new("umount")
-                        .arg(mountpoint)
-                        .output()
-                        .with_context(|| "failed to execute umount command")?;
-
-                    // failed to unmount so fail fully
-                    if !result.status.success() {
-                        return Err(Error::msg(format!("failed to unmount {}", mountpoint)));
-                    }
-                }
-            },
-            // im gonna ignore errors and return here
-            _ => return Ok(()),
+        Ok(())
+    })?;
+
+    log.step("mounts", || {
+        for action in base_mount_actions() {
+            action.execute()?;
         }
-    }
 
-    // if source path is a directory create mountpoint directory
-    if source_path.is_dir() {
-        fs::create_dir_all(mountpoint_path)
-            .with_context(|| format!("failed to create directory {}", mountpoint))?;
-    } else if source_path.is_file() {
-        // if parent directory does not exist create it
-        if let Some(parent_path) = source_path.parent() {
-            if !parent_path.exists() {
-                fs::create_dir_all(parent_path)
-                    .with_context(|| format!("failed to create parent directory {:?}", parent_path))?;
+        for i in load_mount_spec().mounts() {
+            if mounts::bind_mount(&i.source(), &i.destination, i.read_only)? && details {
+                log.info("mounts", &format!("mounted {} at {}", i.source(), i.destination));
             }
         }
 
-        let _ = fs::File::create(&source_path)
-            .with_context(|| format!("failed to create file {}", source));
-    }
+        Ok(())
+    })?;
 
-    // default flags to rslave
-    let mount_flags = if flags.len() > 0 {
-        flags
-    } else {
-        vec!["rslave".into()]
-    };
-
-    let result = Command::new("mount")
-        .args(["--rbind", "-o", &mount_flags.join(","), source_path.as_path().to_str().unwrap(), mountpoint])
-        .output()
-        .with_context(|| "failed to execute mount command")?;
-
-    if !result.status.success() {
-        return Err(Error::msg(format!("mounting {} at {} has failed", source, mountpoint)));
-    }
+    // TODO get user name and mount for ostree systems
+    // if Path::new("/var/home/USER").exists() {
+    //     bind_mount("/run/host/var/home/USER", "/home/USER")?;
+    // }
 
-    Ok(())
-}
+    // TODO find sockets
 
-/// Calls mount command and checks for success generating a clearer error messages on failure
-fn mount(args: Vec<&'static str>) -> Result<()> {
-    let result = Command::new("mount")
-        .args(&args)
-        .output()
-        .with_context(|| "failed to execute mount")?;
+    log.step("network", || {
+        if let Err(err) = network::setup_hostname(details) {
+            log.warning("network", &err);
+        }
 
-    if !result.status.success() {
-        return Err(Error::msg(format!("failed to execute 'mount {}'", args.join(" "))));
-    }
+        if let Err(err) = network::link_resolv_conf(details) {
+            log.warning("network", &err);
+        }
 
-    Ok(())
-}
+        Ok(())
+    })?;
 
-pub fn cmd_init(args: &Cli, manager: &ContainerManager) -> Result<()> {
-    if args.verbose >= 1 {
-        println!("Downloading host-spawn");
-    }
+    // host-exec still works as a subcommand without them
+    log.step("links", || {
+        match links::install_links() {
+            Ok(installed) if details => for i in installed {
+                log.info("links", &format!("installed {}", i.display()));
+            },
+            Ok(_) => {},
+            Err(err) => log.warning("links", &err),
+        }
 
-    // TODO move all of to host
+        Ok(())
+    })?;
 
-    if args.verbose >= 1 {
-        println!("Setting up mounts");
-    }
+    // rules can change between starts
+    log.step("hooks", || {
+        let enabled = !super::command_not_found::load_rules().is_disabled();
+        if let Err(err) = shell_hooks::install_hooks(enabled, details) {
+            log.warning("hooks", &err);
+        }
 
-    mount(vec!["-t", "devpts", "devpts", "-o", "noexec,nosuid,newinstance,ptmxmode=0666,mode=0620", "/dev/pts/"])?;
+        Ok(())
+    })?;
 
-    mount(vec!["--bind", "/dev/pts/ptmx", "/dev/ptmx"])?;
+    log.step("user", || {
+        match user::HostUser::from_env() {
+            Ok(host_user) => user::setup_user(&host_user, details),
+            // containers from older versions do not know about the host user
+            Err(err) => {
+                log.warning("user", &err.context("skipping user setup"));
+                Ok(())
+            },
+        }
+    })?;
 
-    mount(vec!["--make-rshared", "/"])?;
+    // let the host know that the container is ready
+    let marker = Path::new(crate::INIT_MARKER);
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {:?}", parent))?;
+    }
+    fs::File::create(marker)
+        .with_context(|| format!("failed to create init marker {:?}", marker))?;
 
-    // RO mounts
-    for i in [
-        "/etc/localtime",
-        "/var/lib/systemd/coredump",
-        "/var/log/journal",
-    ] {
-        let path = format!("/run/host{}", i);
-        let flags = get_locked_mount_flags(&path)
-            .context(format!("could not get mount flags for {}", &path))?;
+    log.info("init", "container initialized");
 
-        bind_mount(&i, path.as_str(), flags)?;
+    // when ran manually there is nothing to supervise
+    if !supervisor::is_pid1() {
+        return Ok(());
     }
 
-    // TODO get user name and mount for ostree systems
-    // if Path::new("/var/home/USER").exists() {
-    //     bind_mount("/run/host/var/home/USER", "/home/USER")?;
-    // }
+    // systemd takes over as PID 1
+    if let Some(path) = systemd {
+        log.info("init", &format!("starting {}", path.display()));
 
-    // RW mounts
-    for i in [
-        "/etc/host.conf",
-        "/etc/machine-id",
-        "/media",
-        "/mnt",
-        "/run/libvirt",
-        "/run/media",
-        "/run/netconfig/",
-        "/run/systemd/journal",
-        "/run/systemd/resolve/",
-        "/run/systemd/seats",
-        "/run/systemd/sessions",
-        "/run/systemd/users",
-        "/run/udev",
-        "/var/lib/libvirt",
-        "/var/mnt",
-    ] {
-        let path = format!("/run/host{}", i);
-        let flags = get_locked_mount_flags(&path)
-            .context(format!("could not get mount flags for {}", &path))?;
-
-        bind_mount(&i, path.as_str(), flags)?;
+        return Err(systemd::exec_systemd(&path));
     }
 
-    // TODO find sockets
-
-    Ok(())
+    supervisor::run(args.verbose >= 1)
 }
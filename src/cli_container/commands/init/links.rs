@@ -0,0 +1,36 @@
+//! Links that make the executable callable as host-exec and other commands executed on the host
+
+use super::super::host_exec::HOST_EXEC_LINKS;
+use crate::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory where the links are installed, it comes before /usr/bin in PATH
+const LINK_DIR: &str = "/usr/local/bin";
+
+/// Path of the executable inside the container
+const EXECUTABLE: &str = "/lm";
+
+/// Installs missing links, existing files are left alone so they can be replaced by the user,
+/// returns paths of the installed links
+pub fn install_links() -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(LINK_DIR)
+        .with_context(|| format!("failed to create directory {:?}", LINK_DIR))?;
+
+    let mut installed = vec![];
+    for name in HOST_EXEC_LINKS {
+        let path = Path::new(LINK_DIR).join(name);
+
+        // checks the link itself not its target
+        if path.symlink_metadata().is_ok() {
+            continue;
+        }
+
+        std::os::unix::fs::symlink(EXECUTABLE, &path)
+            .with_context(|| format!("failed to create link {:?}", path))?;
+
+        installed.push(path);
+    }
+
+    Ok(installed)
+}
@@ -0,0 +1,74 @@
+//! Recording init steps into the log file and the container output
+
+use crate::init_log::{InitEvent, InitRecord, INIT_LOG_FILE};
+use crate::util::format_timestamp;
+use crate::{Error, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+
+pub struct InitLogger {
+    file: Option<fs::File>,
+
+    /// Print records to output, warnings and failures are always printed
+    echo: bool,
+}
+
+impl InitLogger {
+    /// Starts a new log, log of the previous run is overwritten
+    pub fn new(echo: bool) -> Self {
+        let path = Path::new(INIT_LOG_FILE);
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        let file = fs::File::create(path)
+            .inspect_err(|err| eprintln!("WARNING: failed to create init log {:?}: {}", path, err))
+            .ok();
+
+        Self { file, echo }
+    }
+
+    fn write(&self, step: &str, event: InitEvent, duration_ms: Option<u128>, message: Option<String>) {
+        let line = InitRecord {
+            time: format_timestamp(SystemTime::now()),
+            step: step.into(),
+            event,
+            duration_ms,
+            message,
+        }.to_line();
+
+        if let Some(mut file) = self.file.as_ref() {
+            let _ = writeln!(file, "{}", line);
+        }
+
+        if self.echo || matches!(event, InitEvent::Warning | InitEvent::Failed) {
+            println!("{}", line);
+        }
+    }
+
+    pub fn info(&self, step: &str, message: &str) {
+        self.write(step, InitEvent::Info, None, Some(message.into()));
+    }
+
+    pub fn warning(&self, step: &str, err: &Error) {
+        self.write(step, InitEvent::Warning, None, Some(format!("{:#}", err)));
+    }
+
+    /// Runs the step recording when it started and how long it took
+    pub fn step<T>(&self, name: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.write(name, InitEvent::Start, None, None);
+
+        let start = Instant::now();
+        let result = f();
+        let duration = Some(start.elapsed().as_millis());
+
+        match &result {
+            Ok(_) => self.write(name, InitEvent::Done, duration, None),
+            Err(err) => self.write(name, InitEvent::Failed, duration, Some(format!("{:#}", err))),
+        }
+
+        result
+    }
+}
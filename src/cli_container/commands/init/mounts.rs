@@ -0,0 +1,370 @@
+//! Mounting using syscalls directly with mount table read from /proc/self/mountinfo, minimal
+//! images often lack util-linux and busybox tools do not support everything
+
+use crate::mount_spec::HOST_ROOT;
+use crate::{Context, Error, Result};
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Flags that cannot be changed on bind mounts in user namespaces so they have to be kept, atime
+/// flags are locked as a whole so the one of the source is passed on remount
+const LOCKED_FLAGS: [(&str, libc::c_ulong); 8] = [
+    ("ro", libc::MS_RDONLY),
+    ("nodev", libc::MS_NODEV),
+    ("noexec", libc::MS_NOEXEC),
+    ("nosuid", libc::MS_NOSUID),
+    ("noatime", libc::MS_NOATIME),
+    ("nodiratime", libc::MS_NODIRATIME),
+    ("relatime", libc::MS_RELATIME),
+    ("strictatime", libc::MS_STRICTATIME),
+];
+
+/// Single line of /proc/self/mountinfo
+#[derive(Debug, Clone)]
+pub struct MountEntry {
+    pub mount_point: PathBuf,
+
+    /// Per mount options (eg. 'rw', 'nosuid')
+    pub options: Vec<String>,
+}
+
+impl MountEntry {
+    /// Returns flags of the mount that have to be kept when binding it
+    pub fn locked_flags(&self) -> libc::c_ulong {
+        LOCKED_FLAGS.iter()
+            .filter(|(name, _)| self.options.iter().any(|x| x == name))
+            .fold(0, |acc, (_, flag)| acc | flag)
+    }
+}
+
+/// Decodes octal escapes used for spaces and other special characters in paths
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() && bytes[i + 1..i + 4].iter().all(|x| (b'0'..=b'7').contains(x)) {
+            let octal = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or_default();
+            if let Ok(x) = u8::from_str_radix(octal, 8) {
+                result.push(x);
+                i += 4;
+                continue;
+            }
+        }
+
+        result.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// Parses a line in format described in proc(5)
+fn parse_mountinfo_line(line: &str) -> Option<MountEntry> {
+    // optional fields end with a single hyphen, nothing after it is needed
+    let (before, _) = line.split_once(" - ")?;
+
+    let before: Vec<&str> = before.split(' ').collect();
+
+    Some(MountEntry {
+        mount_point: PathBuf::from(unescape(before.get(4)?)),
+        options: before.get(5)?.split(',').map(|x| x.to_string()).collect(),
+    })
+}
+
+/// Reads mount table of the current mount namespace, mounts are in order they were mounted in
+pub fn read_mountinfo() -> Result<Vec<MountEntry>> {
+    let content = fs::read_to_string("/proc/self/mountinfo")
+        .with_context(|| "failed to read /proc/self/mountinfo")?;
+
+    content.lines()
+        .map(|x| parse_mountinfo_line(x).with_context(|| format!("failed to parse mountinfo line '{}'", x)))
+        .collect()
+}
+
+/// Finds the mount that contains the path, path should be canonical
+pub fn find_mount<'a>(mounts: &'a [MountEntry], path: &Path) -> Option<&'a MountEntry> {
+    // later mounts are on top of earlier ones on the same mountpoint
+    mounts.iter()
+        .filter(|x| path.starts_with(&x.mount_point))
+        .max_by_key(|x| x.mount_point.components().count())
+}
+
+/// Checks if there is anything mounted exactly at the path
+pub fn is_mountpoint(mounts: &[MountEntry], path: &Path) -> bool {
+    mounts.iter().any(|x| x.mount_point == path)
+}
+
+fn to_cstring(value: &str) -> Result<CString> {
+    CString::new(value).with_context(|| format!("invalid path {:?}", value))
+}
+
+/// Calls mount syscall, error message contains all arguments
+pub fn mount(source: Option<&str>, target: &str, fs_type: Option<&str>, flags: libc::c_ulong, data: Option<&str>) -> Result<()> {
+    let c_source = source.map(to_cstring).transpose()?;
+    let c_target = to_cstring(target)?;
+    let c_fs_type = fs_type.map(to_cstring).transpose()?;
+    let c_data = data.map(to_cstring).transpose()?;
+
+    // SAFETY: all strings are valid and null terminated, nulls are allowed by mount(2)
+    let result = unsafe {
+        libc::mount(
+            c_source.as_ref().map_or(std::ptr::null(), |x| x.as_ptr()),
+            c_target.as_ptr(),
+            c_fs_type.as_ref().map_or(std::ptr::null(), |x| x.as_ptr()),
+            flags,
+            c_data.as_ref().map_or(std::ptr::null(), |x| x.as_ptr() as *const libc::c_void),
+        )
+    };
+
+    if result != 0 {
+        return Err(Error::msg(format!(
+            "failed to mount {} at {} (type {}, flags {:#x}, options '{}'): {}",
+            source.unwrap_or("none"),
+            target,
+            fs_type.unwrap_or("none"),
+            flags,
+            data.unwrap_or_default(),
+            std::io::Error::last_os_error(),
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn umount(target: &str) -> Result<()> {
+    let c_target = to_cstring(target)?;
+
+    // SAFETY: the string is valid and null terminated
+    if unsafe { libc::umount2(c_target.as_ptr(), 0) } != 0 {
+        return Err(Error::msg(format!("failed to unmount {}: {}", target, std::io::Error::last_os_error())));
+    }
+
+    Ok(())
+}
+
+/// Resolves symlinks pointing outside of /run/host so they point to the host files instead
+pub fn resolve_host_source(source: &Path) -> Result<PathBuf> {
+    if !source.is_symlink() {
+        return Ok(source.into());
+    }
+
+    let target = source.read_link()
+        .with_context(|| format!("failed to read link {:?}", source))?;
+
+    if target.is_relative() {
+        return Ok(source.parent().unwrap_or(Path::new("/")).join(target));
+    }
+
+    // do not prepend twice
+    if target.starts_with(HOST_ROOT) {
+        return Ok(target);
+    }
+
+    Ok(Path::new(HOST_ROOT).join(target.strip_prefix("/").unwrap_or(&target)))
+}
+
+/// Single step of setting up a mount, kept separate so it can be printed instead of executed
+#[derive(Debug, Clone)]
+pub enum MountAction {
+    RemoveLink(PathBuf),
+    Unmount(PathBuf),
+    CreateDir(PathBuf),
+    CreateFile(PathBuf),
+    Mount {
+        source: Option<String>,
+        target: String,
+        fs_type: Option<String>,
+        flags: libc::c_ulong,
+        data: Option<String>,
+    },
+}
+
+/// Names of mount flags used by init, in order they are printed
+const FLAG_NAMES: [(libc::c_ulong, &str); 14] = [
+    (libc::MS_REMOUNT, "remount"),
+    (libc::MS_BIND, "bind"),
+    (libc::MS_REC, "rec"),
+    (libc::MS_SHARED, "shared"),
+    (libc::MS_SLAVE, "slave"),
+    (libc::MS_RDONLY, "ro"),
+    (libc::MS_NODEV, "nodev"),
+    (libc::MS_NOEXEC, "noexec"),
+    (libc::MS_NOSUID, "nosuid"),
+    (libc::MS_NOATIME, "noatime"),
+    (libc::MS_NODIRATIME, "nodiratime"),
+    (libc::MS_RELATIME, "relatime"),
+    (libc::MS_STRICTATIME, "strictatime"),
+    (libc::MS_PRIVATE, "private"),
+];
+
+fn flags_to_string(flags: libc::c_ulong) -> String {
+    FLAG_NAMES.iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl MountAction {
+    pub fn mount(source: Option<&str>, target: &str, fs_type: Option<&str>, flags: libc::c_ulong, data: Option<&str>) -> Self {
+        Self::Mount {
+            source: source.map(|x| x.into()),
+            target: target.into(),
+            fs_type: fs_type.map(|x| x.into()),
+            flags,
+            data: data.map(|x| x.into()),
+        }
+    }
+
+    pub fn execute(&self) -> Result<()> {
+        match self {
+            Self::RemoveLink(path) => fs::remove_file(path)
+                .with_context(|| format!("failed to delete link {:?}", path)),
+            Self::Unmount(path) => umount(&path.to_string_lossy()),
+            Self::CreateDir(path) => fs::create_dir_all(path)
+                .with_context(|| format!("failed to create directory {:?}", path)),
+            Self::CreateFile(path) => fs::File::create(path)
+                .map(|_| ())
+                .with_context(|| format!("failed to create file {:?}", path)),
+            Self::Mount { source, target, fs_type, flags, data } => mount(source.as_deref(), target, fs_type.as_deref(), *flags, data.as_deref()),
+        }
+    }
+}
+
+impl std::fmt::Display for MountAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RemoveLink(path) => write!(f, "remove link {}", path.display()),
+            Self::Unmount(path) => write!(f, "unmount {}", path.display()),
+            Self::CreateDir(path) => write!(f, "create directory {}", path.display()),
+            Self::CreateFile(path) => write!(f, "create file {}", path.display()),
+            Self::Mount { source, target, fs_type, flags, data } => {
+                write!(f, "mount {} at {}", source.as_deref().unwrap_or("none"), target)?;
+
+                if let Some(fs_type) = fs_type {
+                    write!(f, " type {}", fs_type)?;
+                }
+
+                if *flags != 0 {
+                    write!(f, " flags {}", flags_to_string(*flags))?;
+                }
+
+                if let Some(data) = data {
+                    write!(f, " options {}", data)?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Returns steps to recursively bind mount source at mountpoint as a slave, flags locked on the
+/// source mount are kept, returns None if the source does not exist
+pub fn plan_bind_mount(mounts: &[MountEntry], source: &str, mountpoint: &str, read_only: bool) -> Result<Option<Vec<MountAction>>> {
+    let source_path = resolve_host_source(Path::new(source))?;
+
+    // not all hosts have all of the paths
+    let Ok(canonical_source) = source_path.canonicalize() else {
+        return Ok(None);
+    };
+
+    let mountpoint_path = Path::new(mountpoint);
+    let mut actions = vec![];
+
+    if mountpoint_path.is_symlink() {
+        actions.push(MountAction::RemoveLink(mountpoint_path.into()));
+    } else if is_mountpoint(mounts, mountpoint_path) {
+        actions.push(MountAction::Unmount(mountpoint_path.into()));
+    }
+
+    if canonical_source.is_dir() {
+        if mountpoint_path.is_symlink() || !mountpoint_path.is_dir() {
+            actions.push(MountAction::CreateDir(mountpoint_path.into()));
+        }
+    } else if mountpoint_path.is_symlink() || !mountpoint_path.exists() {
+        if let Some(parent) = mountpoint_path.parent().filter(|x| !x.exists()) {
+            actions.push(MountAction::CreateDir(parent.into()));
+        }
+
+        actions.push(MountAction::CreateFile(mountpoint_path.into()));
+    }
+
+    let source_str = canonical_source.to_str()
+        .with_context(|| format!("invalid path {:?}", canonical_source))?;
+
+    actions.push(MountAction::mount(Some(source_str), mountpoint, None, libc::MS_BIND | libc::MS_REC, None));
+
+    let mut flags = find_mount(mounts, &canonical_source)
+        .map(|x| x.locked_flags())
+        .unwrap_or_default();
+
+    if read_only {
+        flags |= libc::MS_RDONLY;
+    }
+
+    // flags of bind mounts can only be changed by remounting
+    if flags != 0 {
+        actions.push(MountAction::mount(None, mountpoint, None, libc::MS_REMOUNT | libc::MS_BIND | flags, None));
+    }
+
+    actions.push(MountAction::mount(None, mountpoint, None, libc::MS_SLAVE | libc::MS_REC, None));
+
+    Ok(Some(actions))
+}
+
+/// Recursively bind mounts source at mountpoint, see `plan_bind_mount`, returns false if the
+/// source does not exist
+pub fn bind_mount(source: &str, mountpoint: &str, read_only: bool) -> Result<bool> {
+    let Some(actions) = plan_bind_mount(&read_mountinfo()?, source, mountpoint, read_only)? else {
+        return Ok(false);
+    };
+
+    for action in actions {
+        action.execute()?;
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_octal() {
+        assert_eq!(unescape(r"/mnt/My\040Drive"), "/mnt/My Drive");
+        assert_eq!(unescape(r"/a\011b\012c\134d"), "/a\tb\nc\\d");
+
+        // incomplete or invalid escapes are kept
+        assert_eq!(unescape(r"/a\04"), r"/a\04");
+        assert_eq!(unescape(r"/a\089"), r"/a\089");
+        assert_eq!(unescape(r"/a\"), r"/a\");
+    }
+
+    #[test]
+    fn mountinfo_lines() {
+        let entry = parse_mountinfo_line("36 35 98:0 /mnt1 /mnt/My\\040Drive rw,noatime master:1 - ext3 /dev/root rw,errors=continue").unwrap();
+        assert_eq!(entry.mount_point, Path::new("/mnt/My Drive"));
+        assert_eq!(entry.options, ["rw", "noatime"]);
+        assert_eq!(entry.locked_flags(), libc::MS_NOATIME);
+
+        // any number of optional fields
+        let entry = parse_mountinfo_line("25 1 0:22 / /run/host ro,nosuid,nodev,relatime shared:5 master:3 propagate_from:2 - tmpfs tmpfs ro").unwrap();
+        assert_eq!(entry.mount_point, Path::new("/run/host"));
+        assert_eq!(entry.locked_flags(), libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV | libc::MS_RELATIME);
+
+        let entry = parse_mountinfo_line("23 28 0:22 / /proc rw,nosuid,nodev,noexec - proc proc rw").unwrap();
+        assert_eq!(entry.locked_flags(), libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC);
+    }
+
+    #[test]
+    fn malformed_mountinfo_lines() {
+        for line in ["", "23 28 0:22 / /proc rw", "23 28 0:22 / - proc proc rw", "23 28 0:22 /proc rw,relatime proc proc rw"] {
+            assert!(parse_mountinfo_line(line).is_none(), "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Applying hostname and keeping name resolution files in sync with the host
+
+use super::mounts::{is_mountpoint, read_mountinfo, resolve_host_source, umount};
+use crate::host_exec::CONTAINER_SOCKET_DIR;
+use crate::{Context, Error, Result};
+use std::fs;
+use std::path::Path;
+
+/// Comment appended to the line in /etc/hosts managed by init so it can be replaced on rename
+const HOSTS_COMMENT: &str = "# legumemanager";
+
+const HOST_RESOLV_CONF: &str = "/run/host/etc/resolv.conf";
+
+/// Returns hostname set using `set-hostname` if any
+fn read_hostname_override() -> Option<String> {
+    fs::read_to_string(Path::new(CONTAINER_SOCKET_DIR).join(crate::HOSTNAME_FILE)).ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+}
+
+fn get_current_hostname() -> Result<String> {
+    gethostname::gethostname().into_string()
+        .map_err(|_| Error::msg("current hostname is not valid UTF-8"))
+}
+
+fn set_hostname(hostname: &str) -> Result<()> {
+    // SAFETY: the pointer and length describe a valid buffer
+    if unsafe { libc::sethostname(hostname.as_ptr() as *const libc::c_char, hostname.len()) } != 0 {
+        return Err(Error::msg(format!("failed to set hostname to '{}': {}", hostname, std::io::Error::last_os_error())));
+    }
+
+    Ok(())
+}
+
+/// Makes sure the hostname resolves to loopback, line added previously by init is replaced
+fn update_hosts(hostname: &str) -> Result<()> {
+    let content = fs::read_to_string("/etc/hosts").unwrap_or_default();
+
+    let mut lines: Vec<&str> = content.lines()
+        .filter(|x| !x.ends_with(HOSTS_COMMENT))
+        .collect();
+
+    // the manager may have already added it
+    let resolves = lines.iter()
+        .map(|x| x.split('#').next().unwrap_or_default())
+        .any(|x| x.split_whitespace().skip(1).any(|x| x == hostname));
+
+    let entry = format!("127.0.1.1\t{} {}", hostname, HOSTS_COMMENT);
+    if !resolves {
+        lines.push(&entry);
+    }
+
+    let mut data = lines.join("\n");
+    data.push('\n');
+
+    if data != content {
+        fs::write("/etc/hosts", data)
+            .with_context(|| "failed to write /etc/hosts")?;
+    }
+
+    Ok(())
+}
+
+/// Sets the hostname, using the one from `set-hostname` over the container hostname, and makes it
+/// resolvable
+pub fn setup_hostname(verbose: bool) -> Result<()> {
+    let current = get_current_hostname()?;
+    let hostname = read_hostname_override().unwrap_or(current.clone());
+
+    if hostname != current {
+        set_hostname(&hostname)?;
+    }
+
+    fs::write("/etc/hostname", format!("{}\n", hostname))
+        .with_context(|| "failed to write /etc/hostname")?;
+
+    update_hosts(&hostname)?;
+
+    if verbose {
+        println!("Hostname set to {}", hostname);
+    }
+
+    Ok(())
+}
+
+/// Links resolv.conf to the one of the host so changes on the host apply right away, on the host
+/// it is often a link to systemd-resolved so the link is resolved inside the host root first
+pub fn link_resolv_conf(verbose: bool) -> Result<()> {
+    let target = resolve_host_source(Path::new(HOST_RESOLV_CONF))?;
+    if !target.exists() {
+        return Ok(());
+    }
+
+    let path = Path::new("/etc/resolv.conf");
+    if fs::read_link(path).is_ok_and(|x| x == target) {
+        return Ok(());
+    }
+
+    // container managers mount their own copy over it
+    if is_mountpoint(&read_mountinfo()?, path) {
+        umount("/etc/resolv.conf")?;
+    }
+
+    // images sometimes link it to systemd-resolved which is not running
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(path)
+            .with_context(|| "failed to remove /etc/resolv.conf")?;
+    }
+
+    std::os::unix::fs::symlink(&target, path)
+        .with_context(|| format!("failed to link /etc/resolv.conf to {:?}", target))?;
+
+    if verbose {
+        println!("Linked /etc/resolv.conf to {}", target.display());
+    }
+
+    Ok(())
+}
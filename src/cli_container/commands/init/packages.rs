@@ -0,0 +1,295 @@
+//! Installing tools init and the user need on minimal images, the distribution is detected from
+//! /etc/os-release
+
+use crate::{Context, Error, Result};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Marker created once all essential packages are installed, unlike the init marker it has to
+/// survive restarts
+const BOOTSTRAPPED_MARKER: &str = "/var/lib/legumemanager/bootstrapped";
+
+/// Package managers supported for installing essentials
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+    Apk,
+    Zypper,
+    Xbps,
+}
+
+impl PackageManager {
+    /// Maps distribution id from os-release to its package manager
+    fn from_distro_id(id: &str) -> Option<Self> {
+        match id {
+            "debian" | "ubuntu" | "linuxmint" | "pop" | "elementary" | "kali" | "raspbian" => Some(Self::Apt),
+            "fedora" | "rhel" | "centos" | "rocky" | "almalinux" | "ol" | "amzn" => Some(Self::Dnf),
+            "arch" | "archarm" | "manjaro" | "endeavouros" | "artix" => Some(Self::Pacman),
+            "alpine" | "postmarketos" => Some(Self::Apk),
+            "opensuse" | "opensuse-leap" | "opensuse-tumbleweed" | "sles" | "suse" => Some(Self::Zypper),
+            "void" => Some(Self::Xbps),
+            _ => None,
+        }
+    }
+
+    /// Executable that is used to detect the package manager when os-release is not helpful
+    fn executable(&self) -> &'static str {
+        match self {
+            Self::Apt => "apt-get",
+            Self::Dnf => "dnf",
+            Self::Pacman => "pacman",
+            Self::Apk => "apk",
+            Self::Zypper => "zypper",
+            Self::Xbps => "xbps-install",
+        }
+    }
+
+    /// Command that refreshes package index, if the package manager needs it
+    fn update_command(&self) -> Option<Vec<&'static str>> {
+        match self {
+            Self::Apt => Some(vec!["apt-get", "update"]),
+            Self::Xbps => Some(vec!["xbps-install", "-S"]),
+            _ => None,
+        }
+    }
+
+    /// Non-interactive install command without the packages
+    fn install_command(&self) -> Vec<&'static str> {
+        match self {
+            Self::Apt => vec!["apt-get", "install", "-y", "--no-install-recommends"],
+            Self::Dnf => vec!["dnf", "install", "-y"],
+            Self::Pacman => vec!["pacman", "-Sy", "--noconfirm", "--needed"],
+            Self::Apk => vec!["apk", "add", "--no-cache"],
+            Self::Zypper => vec!["zypper", "--non-interactive", "install"],
+            Self::Xbps => vec!["xbps-install", "-y"],
+        }
+    }
+}
+
+/// Executable required inside the container and package providing it, named the same on all
+/// supported distributions
+struct Essential {
+    executable: &'static str,
+    package: &'static str,
+}
+
+// mounting is done using syscalls so only sudo is needed, it is used to enter the container as
+// the user
+const ESSENTIALS: [Essential; 1] = [
+    Essential { executable: "sudo", package: "sudo" },
+];
+
+/// Parses os-release returning ID followed by IDs from ID_LIKE
+fn parse_distro_ids(content: &str) -> Vec<String> {
+    let mut id = vec![];
+    let mut id_like = vec![];
+
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim().trim_matches(|x| x == '"' || x == '\'');
+        match key.trim() {
+            "ID" => id.push(value.to_string()),
+            "ID_LIKE" => id_like.extend(value.split_whitespace().map(|x| x.to_string())),
+            _ => {},
+        }
+    }
+
+    id.extend(id_like);
+    id
+}
+
+fn read_distro_ids() -> Vec<String> {
+    let content = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .unwrap_or_default();
+
+    parse_distro_ids(&content)
+}
+
+/// Checks if executable exists in PATH, `which` is often missing on minimal images so PATH is
+/// searched directly
+fn executable_exists(name: &str) -> bool {
+    let path = std::env::var("PATH").unwrap_or_else(|_| "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".into());
+
+    path.split(':')
+        .filter(|x| !x.is_empty())
+        .any(|x| Path::new(x).join(name).is_file())
+}
+
+/// Returns package manager of the first known distribution id
+fn package_manager_of(ids: &[String]) -> Option<PackageManager> {
+    ids.iter().find_map(|x| PackageManager::from_distro_id(x))
+}
+
+/// Detects package manager of the distribution, falls back to looking for known package managers
+fn detect_package_manager() -> Option<PackageManager> {
+    package_manager_of(&read_distro_ids())
+        .filter(|x| executable_exists(x.executable()))
+        .or_else(|| {
+            [
+                PackageManager::Apt,
+                PackageManager::Dnf,
+                PackageManager::Pacman,
+                PackageManager::Apk,
+                PackageManager::Zypper,
+                PackageManager::Xbps,
+            ].into_iter().find(|x| executable_exists(x.executable()))
+        })
+}
+
+/// Returns packages providing the missing essentials without duplicates
+fn missing_packages() -> Vec<&'static str> {
+    let mut packages: Vec<&'static str> = vec![];
+
+    for essential in ESSENTIALS.iter().filter(|x| !executable_exists(x.executable)) {
+        if !packages.contains(&essential.package) {
+            packages.push(essential.package);
+        }
+    }
+
+    packages
+}
+
+/// Runs the command, output is only shown when verbose
+fn run(command: &[&str], verbose: bool) -> bool {
+    let (stdout, stderr) = if verbose {
+        (Stdio::inherit(), Stdio::inherit())
+    } else {
+        (Stdio::null(), Stdio::null())
+    };
+
+    Command::new(command[0])
+        .args(&command[1..])
+        .env("DEBIAN_FRONTEND", "noninteractive")
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .status()
+        .is_ok_and(|x| x.success())
+}
+
+/// Installs the packages returning the ones that failed, packages are installed one by one only
+/// if installing all at once fails to find out which ones are the problem
+fn install_packages(manager: PackageManager, packages: &[&'static str], verbose: bool) -> Vec<&'static str> {
+    if let Some(update) = manager.update_command() {
+        // not fatal as install could work with stale index
+        if !run(&update, verbose) {
+            eprintln!("WARNING: failed to update package index using '{}'", update.join(" "));
+        }
+    }
+
+    let install = manager.install_command();
+
+    let mut command = install.clone();
+    command.extend(packages);
+    if run(&command, verbose) {
+        return vec![];
+    } else if packages.len() == 1 {
+        return packages.to_vec();
+    }
+
+    packages.iter()
+        .copied()
+        .filter(|x| {
+            let mut command = install.clone();
+            command.push(x);
+
+            !run(&command, verbose)
+        })
+        .collect()
+}
+
+fn create_marker(path: &str) -> Result<()> {
+    let marker = Path::new(path);
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {:?}", parent))?;
+    }
+
+    fs::File::create(marker)
+        .with_context(|| format!("failed to create marker {:?}", marker))?;
+
+    Ok(())
+}
+
+/// Removes the marker telling the host that packages are being installed when dropped
+struct InstallingMarker;
+
+impl Drop for InstallingMarker {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(crate::BOOTSTRAP_MARKER);
+    }
+}
+
+/// Installs essential packages missing from the image, done only once per container as the
+/// marker is kept until all of them are installed
+pub fn bootstrap(verbose: u8) -> Result<()> {
+    if Path::new(BOOTSTRAPPED_MARKER).exists() {
+        return Ok(());
+    }
+
+    let packages = missing_packages();
+    if !packages.is_empty() {
+        let manager = detect_package_manager()
+            .ok_or_else(|| Error::msg("could not detect package manager of the distribution, essential packages were not installed"))?;
+
+        if verbose >= 1 {
+            println!("Installing {} using {}", packages.join(", "), manager.executable());
+        }
+
+        // the host waits for init longer while it exists
+        create_marker(crate::BOOTSTRAP_MARKER)?;
+        let _installing = InstallingMarker;
+
+        let failed = install_packages(manager, &packages, verbose >= 2);
+        if !failed.is_empty() {
+            return Err(Error::msg(format!(
+                "failed to install packages: {} (using {}), will retry on next start",
+                failed.join(", "),
+                manager.executable(),
+            )));
+        }
+    }
+
+    create_marker(BOOTSTRAPPED_MARKER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn os_release_ids() {
+        let ubuntu = "NAME=\"Ubuntu\"\nVERSION_ID=\"24.04\"\nID=ubuntu\nID_LIKE=debian\nPRETTY_NAME=\"Ubuntu 24.04 LTS\"\n";
+        assert_eq!(parse_distro_ids(ubuntu), ["ubuntu", "debian"]);
+
+        let rocky = "NAME=\"Rocky Linux\"\nID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n";
+        assert_eq!(parse_distro_ids(rocky), ["rocky", "rhel", "centos", "fedora"]);
+
+        assert_eq!(parse_distro_ids("ID='void'\n# comment\n\nBROKEN\n"), ["void"]);
+        assert!(parse_distro_ids("").is_empty());
+    }
+
+    #[test]
+    fn package_manager_from_ids() {
+        let manager = |content: &str| package_manager_of(&parse_distro_ids(content));
+
+        assert_eq!(manager("ID=ubuntu\nID_LIKE=debian"), Some(PackageManager::Apt));
+        assert_eq!(manager("ID=fedora"), Some(PackageManager::Dnf));
+        assert_eq!(manager("ID=alpine"), Some(PackageManager::Apk));
+        assert_eq!(manager("ID=\"opensuse-tumbleweed\"\nID_LIKE=\"opensuse suse\""), Some(PackageManager::Zypper));
+
+        // derivatives are found through ID_LIKE
+        assert_eq!(manager("ID=cachyos\nID_LIKE=arch"), Some(PackageManager::Pacman));
+        assert_eq!(manager("ID=neon\nID_LIKE=\"ubuntu debian\""), Some(PackageManager::Apt));
+
+        assert_eq!(manager("ID=nixos"), None);
+        assert_eq!(manager(""), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Shell hooks that execute commands not found inside the container on the host
+
+use crate::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Hook for bash and zsh
+const HOOK_FILE: &str = "/etc/profile.d/legumemanager-command-not-found.sh";
+
+const HOOK: &str = r#"# installed by legumemanager, executes missing commands on the host if allowed
+if [ -n "$BASH_VERSION" ]; then
+    command_not_found_handle() {
+        /lm command-not-found "$@"
+    }
+elif [ -n "$ZSH_VERSION" ]; then
+    command_not_found_handler() {
+        /lm command-not-found "$@"
+    }
+fi
+"#;
+
+/// Fish reads everything in conf.d on start
+const FISH_HOOK_FILE: &str = "/etc/fish/conf.d/legumemanager-command-not-found.fish";
+
+const FISH_HOOK: &str = r#"# installed by legumemanager, executes missing commands on the host if allowed
+function fish_command_not_found
+    /lm command-not-found $argv
+end
+"#;
+
+/// Files read by interactive shells that are not login shells, they differ between distros
+const SHELL_RC_FILES: [&str; 4] = [
+    "/etc/bash.bashrc",
+    "/etc/bashrc",
+    "/etc/zsh/zshrc",
+    "/etc/zshrc",
+];
+
+/// Line appended to rc files so the hook is loaded by shells that do not read /etc/profile.d
+fn source_line() -> String {
+    format!("[ -r {0} ] && . {0} # legumemanager", HOOK_FILE)
+}
+
+/// Returns content of the rc file with the source line appended, None if it is already there
+fn hook_rc_file(content: &str) -> Option<String> {
+    let line = source_line();
+    if content.lines().any(|x| x == line) {
+        return None;
+    }
+
+    let separator = if content.is_empty() || content.ends_with('\n') { "" } else { "\n" };
+
+    Some(format!("{}{}{}\n", content, separator, line))
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {:?}", parent))?;
+    }
+
+    fs::write(path, content)
+        .with_context(|| format!("failed to write {:?}", path))
+}
+
+/// Installs the hooks, they are removed when disabled so shells keep their own handlers
+pub fn install_hooks(enabled: bool, verbose: bool) -> Result<()> {
+    if !enabled {
+        for i in [HOOK_FILE, FISH_HOOK_FILE] {
+            if Path::new(i).exists() {
+                fs::remove_file(i)
+                    .with_context(|| format!("failed to delete {:?}", i))?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    write_file(Path::new(HOOK_FILE), HOOK)?;
+
+    // fish may not even be installed
+    if Path::new("/etc/fish").is_dir() {
+        write_file(Path::new(FISH_HOOK_FILE), FISH_HOOK)?;
+    }
+
+    for i in SHELL_RC_FILES {
+        let Ok(content) = fs::read_to_string(i) else {
+            continue;
+        };
+
+        let Some(content) = hook_rc_file(&content) else {
+            continue;
+        };
+
+        fs::write(i, content)
+            .with_context(|| format!("failed to write {:?}", i))?;
+
+        if verbose {
+            println!("Hooked command not found handler into {}", i);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rc_file_is_hooked_once() {
+        let hooked = hook_rc_file("alias ll='ls -l'\n").unwrap();
+        assert_eq!(hooked, format!("alias ll='ls -l'\n{}\n", source_line()));
+
+        // init runs on every start
+        assert_eq!(hook_rc_file(&hooked), None);
+    }
+
+    #[test]
+    fn rc_file_without_trailing_newline() {
+        assert_eq!(hook_rc_file("export A=b").unwrap(), format!("export A=b\n{}\n", source_line()));
+        assert_eq!(hook_rc_file("").unwrap(), format!("{}\n", source_line()));
+    }
+}
@@ -0,0 +1,153 @@
+//! Minimal PID 1 supervisor, keeps the container alive after setup, reaps orphans and forwards
+//! termination signals to every process in the container
+
+use crate::{Error, Result};
+use std::time::{Duration, Instant};
+
+/// Signals that stop the container, they are forwarded to all processes
+const STOP_SIGNALS: [i32; 4] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP, libc::SIGQUIT];
+
+/// Time processes have to exit after the signal is forwarded, afterwards they are killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn signal_set() -> libc::sigset_t {
+    // SAFETY: the set is initialized by sigemptyset before use
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGCHLD);
+        for signal in STOP_SIGNALS {
+            libc::sigaddset(&mut set, signal);
+        }
+
+        set
+    }
+}
+
+/// Blocks the handled signals so they are queued until the supervisor is running, should be
+/// called before setup starts as signals without handler are ignored by PID 1
+pub fn block_signals() -> Result<()> {
+    let set = signal_set();
+
+    // SAFETY: the set is valid and old mask is not requested
+    if unsafe { libc::sigprocmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) } != 0 {
+        return Err(Error::msg(format!("failed to block signals: {}", std::io::Error::last_os_error())));
+    }
+
+    Ok(())
+}
+
+/// Returns true if this process is the init of the container
+pub fn is_pid1() -> bool {
+    std::process::id() == 1
+}
+
+/// Reaps all children that have exited, returns false if there are no children left
+fn reap_children() -> bool {
+    loop {
+        let mut status = 0;
+
+        // SAFETY: status is a valid pointer
+        let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+        match pid {
+            // children exist but none have exited
+            0 => return true,
+            x if x > 0 => continue,
+            _ => return std::io::Error::last_os_error().raw_os_error() != Some(libc::ECHILD),
+        }
+    }
+}
+
+/// Checks if there is any process in the container besides init, exec sessions are not children
+/// of init so waiting for children is not enough
+fn has_other_processes() -> bool {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+
+    entries
+        .filter_map(|x| x.ok())
+        .filter_map(|x| x.file_name().to_str().and_then(|x| x.parse::<u32>().ok()))
+        .any(|x| x != std::process::id())
+}
+
+/// Waits for a signal from the set, returns None on timeout
+fn wait_signal(set: &libc::sigset_t, timeout: Option<Duration>) -> Option<i32> {
+    loop {
+        // SAFETY: set is valid and info is not requested
+        let signal = match timeout {
+            Some(timeout) => {
+                let timeout = libc::timespec {
+                    tv_sec: timeout.as_secs() as libc::time_t,
+                    tv_nsec: timeout.subsec_nanos() as libc::c_long,
+                };
+                unsafe { libc::sigtimedwait(set, std::ptr::null_mut(), &timeout) }
+            },
+            None => unsafe { libc::sigwaitinfo(set, std::ptr::null_mut()) },
+        };
+
+        if signal >= 0 {
+            return Some(signal);
+        }
+
+        match std::io::Error::last_os_error().raw_os_error() {
+            Some(libc::EINTR) => continue,
+            _ => return None,
+        }
+    }
+}
+
+/// Forwards the signal to all processes and waits for them to exit, anything left after the
+/// timeout is killed
+fn shutdown(set: &libc::sigset_t, signal: i32, verbose: bool) {
+    if verbose {
+        println!("Received signal {}, stopping all processes", signal);
+    }
+
+    // SAFETY: as PID 1 this signals every other process in the container
+    unsafe { libc::kill(-1, signal) };
+
+    let start = Instant::now();
+    let mut killed = false;
+
+    loop {
+        reap_children();
+
+        if !has_other_processes() {
+            break;
+        }
+
+        if !killed && start.elapsed() >= SHUTDOWN_TIMEOUT {
+            if verbose {
+                println!("Processes did not stop in time, killing them");
+            }
+
+            // SAFETY: same as above
+            unsafe { libc::kill(-1, libc::SIGKILL) };
+            killed = true;
+        }
+
+        // woken up early by exiting children
+        wait_signal(set, Some(Duration::from_millis(100)));
+    }
+}
+
+/// Runs the supervisor loop until a stop signal is received, signals have to be blocked using
+/// `block_signals` beforehand
+pub fn run(verbose: bool) -> Result<()> {
+    let set = signal_set();
+
+    // children could have exited before the loop started
+    reap_children();
+
+    loop {
+        match wait_signal(&set, None) {
+            Some(libc::SIGCHLD) => { reap_children(); },
+            Some(signal) => {
+                shutdown(&set, signal, verbose);
+                return Ok(());
+            },
+            None => return Err(Error::msg(format!("failed to wait for signals: {}", std::io::Error::last_os_error()))),
+        }
+    }
+}
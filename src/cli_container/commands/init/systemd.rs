@@ -0,0 +1,44 @@
+//! Handing off to systemd in containers created with --init
+
+use crate::Error;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Locations of systemd executable in order of preference
+const SYSTEMD_PATHS: [&str; 2] = [
+    "/usr/lib/systemd/systemd",
+    "/lib/systemd/systemd",
+];
+
+/// Finds systemd inside the image, /sbin/init is only used if it is systemd as other init systems
+/// do not know how to run in a container
+pub fn find_systemd() -> Option<PathBuf> {
+    for path in SYSTEMD_PATHS {
+        if Path::new(path).is_file() {
+            return Some(path.into());
+        }
+    }
+
+    let init = Path::new("/sbin/init").canonicalize().ok()?;
+    if init.to_string_lossy().contains("systemd") {
+        return Some("/sbin/init".into());
+    }
+
+    None
+}
+
+/// Returns error explaining that the image has no systemd
+pub fn missing_systemd_error() -> Error {
+    Error::msg(concat!(
+        "container was created with --init but the image does not contain systemd\n",
+        "hint: install systemd in the image (eg. 'dnf install systemd' or 'apt install systemd') or recreate the container without --init",
+    ))
+}
+
+/// Replaces current process with systemd, returns only on failure
+pub fn exec_systemd(path: &Path) -> Error {
+    let err = Command::new(path).exec();
+
+    Error::msg(format!("failed to execute {:?}: {}", path, err))
+}
@@ -0,0 +1,446 @@
+//! Creating user matching the host user inside the container, the databases are edited directly as
+//! minimal images often do not have useradd
+
+use crate::{Context, Error, Result};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Groups that are allowed to use sudo on different distributions
+const ADMIN_GROUPS: [&str; 2] = ["wheel", "sudo"];
+
+/// Shell used when the host shell is not available inside the container
+const FALLBACK_SHELLS: [&str; 2] = ["/bin/bash", "/bin/sh"];
+
+const SUDOERS_PATH: &str = "/etc/sudoers.d/legumemanager";
+
+/// Host user as passed through environment variables at creation
+#[derive(Debug, Clone)]
+pub struct HostUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: String,
+    pub shell: String,
+}
+
+fn get_env(key: &str) -> Result<String> {
+    std::env::var(key)
+        .with_context(|| format!("environment variable '{}' is not defined, the container was created by an older version", key))
+}
+
+impl HostUser {
+    pub fn from_env() -> Result<Self> {
+        let uid = get_env("manager_uid")?;
+        let gid = get_env("manager_gid")?;
+
+        Ok(Self {
+            name: get_env("manager_user")?,
+            uid: uid.parse().with_context(|| format!("invalid uid '{}'", uid))?,
+            gid: gid.parse().with_context(|| format!("invalid gid '{}'", gid))?,
+            home: get_env("HOME")?,
+            shell: std::env::var("manager_user_shell").unwrap_or_default(),
+        })
+    }
+}
+
+/// Reads lines of colon separated database like /etc/passwd, missing file is treated as empty
+fn read_database(path: &str) -> Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(x) => Ok(x.lines().map(|x| x.to_string()).collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path)),
+    }
+}
+
+fn write_database(path: &str, lines: &[String]) -> Result<()> {
+    let mut data = lines.join("\n");
+    data.push('\n');
+
+    fs::write(path, data)
+        .with_context(|| format!("failed to write {}", path))
+}
+
+/// Returns the entry field at index
+fn field(line: &str, index: usize) -> Option<&str> {
+    line.split(':').nth(index)
+}
+
+/// Replaces replaced users with the name in comma separated member list at the index
+fn rename_members(line: &str, index: usize, replaced: &[String], name: &str) -> String {
+    let mut fields: Vec<&str> = line.split(':').collect();
+
+    let mut members: Vec<&str> = vec![];
+    for member in fields.get(index).copied().unwrap_or_default().split(',').filter(|x| !x.is_empty()) {
+        let member = if replaced.iter().any(|x| x == member) { name } else { member };
+        if !members.contains(&member) {
+            members.push(member);
+        }
+    }
+
+    let members = members.join(",");
+    if let Some(x) = fields.get_mut(index) {
+        *x = &members;
+    }
+
+    fields.join(":")
+}
+
+/// Picks host shell if it exists in the container, otherwise a fallback
+fn pick_shell(shell: &str) -> &str {
+    if !shell.is_empty() && Path::new(shell).exists() {
+        return shell;
+    }
+
+    FALLBACK_SHELLS.into_iter()
+        .find(|x| Path::new(x).exists())
+        .unwrap_or("/bin/sh")
+}
+
+/// Puts the host user into passwd lines, user owning the uid is replaced as with two users with
+/// the same uid the first one would be used everywhere, returns names of the replaced users
+fn update_passwd(passwd: &mut Vec<String>, user: &HostUser, shell: &str) -> Vec<String> {
+    let entry = format!("{}:x:{}:{}:{}:{}:{}", user.name, user.uid, user.gid, user.name, user.home, shell);
+    let uid = user.uid.to_string();
+    let mut replaced = vec![];
+    let mut added = false;
+
+    passwd.retain_mut(|line| {
+        let same_name = field(line, 0) == Some(user.name.as_str());
+        if !same_name && field(line, 2) != Some(uid.as_str()) {
+            return true;
+        }
+
+        if !same_name {
+            replaced.push(field(line, 0).unwrap_or_default().to_string());
+        }
+
+        // only the first one is kept
+        if added {
+            return false;
+        }
+
+        *line = entry.clone();
+        added = true;
+
+        true
+    });
+
+    if !added {
+        passwd.push(entry);
+    }
+
+    replaced
+}
+
+/// Puts the user into shadow lines with locked password, sudo does not need it
+fn update_shadow(shadow: &mut Vec<String>, user: &HostUser, replaced: &[String]) {
+    shadow.retain(|x| !replaced.iter().any(|y| field(x, 0) == Some(y.as_str())));
+
+    if !shadow.iter().any(|x| field(x, 0) == Some(user.name.as_str())) {
+        shadow.push(format!("{}:!::0:99999:7:::", user.name));
+    }
+}
+
+/// Puts group with the gid into group lines, returns its name and previous name if it was renamed
+///
+/// Group owning the gid is renamed only if it belonged to a replaced user, shared groups like
+/// 'users' are kept as they are
+fn update_group(groups: &mut Vec<String>, user: &HostUser, replaced: &[String]) -> (String, Option<String>) {
+    for line in groups.iter_mut() {
+        *line = rename_members(line, 3, replaced, &user.name);
+    }
+
+    let gid = user.gid.to_string();
+    let name_taken = groups.iter().any(|x| field(x, 0) == Some(user.name.as_str()));
+
+    if let Some(line) = groups.iter_mut().find(|x| field(x, 2) == Some(gid.as_str())) {
+        let current = field(line, 0).unwrap_or_default().to_string();
+
+        if !name_taken && replaced.contains(&current) {
+            *line = format!("{}{}", user.name, &line[current.len()..]);
+            return (user.name.clone(), Some(current));
+        }
+
+        return (current, None);
+    }
+
+    // name is taken by a group with different gid
+    let name = if name_taken { format!("{}-host", user.name) } else { user.name.clone() };
+    groups.push(format!("{}:x:{}:", name, user.gid));
+
+    (name, None)
+}
+
+/// Applies changes of `update_group` to gshadow lines
+fn update_gshadow(gshadow: &mut Vec<String>, group: &str, previous: Option<&str>, user: &HostUser, replaced: &[String]) {
+    for line in gshadow.iter_mut() {
+        *line = rename_members(&rename_members(line, 2, replaced, &user.name), 3, replaced, &user.name);
+
+        if let Some(previous) = previous.filter(|x| field(line, 0) == Some(x)) {
+            *line = format!("{}{}", group, &line[previous.len()..]);
+        }
+    }
+
+    if !gshadow.iter().any(|x| field(x, 0) == Some(group)) {
+        gshadow.push(format!("{}:!::", group));
+    }
+}
+
+/// Creates the user, existing user with the same name or uid is updated to match the host,
+/// returns names of the replaced users
+fn ensure_user(user: &HostUser, shell: &str) -> Result<Vec<String>> {
+    let mut passwd = read_database("/etc/passwd")?;
+    let replaced = update_passwd(&mut passwd, user, shell);
+    write_database("/etc/passwd", &passwd)?;
+
+    if Path::new("/etc/shadow").exists() {
+        let mut shadow = read_database("/etc/shadow")?;
+        update_shadow(&mut shadow, user, &replaced);
+        write_database("/etc/shadow", &shadow)?;
+    }
+
+    Ok(replaced)
+}
+
+/// Creates group with the gid unless it already exists, returns name of the group
+fn ensure_group(user: &HostUser, replaced: &[String]) -> Result<String> {
+    let mut groups = read_database("/etc/group")?;
+    let (name, previous) = update_group(&mut groups, user, replaced);
+    write_database("/etc/group", &groups)?;
+
+    if Path::new("/etc/gshadow").exists() {
+        let mut gshadow = read_database("/etc/gshadow")?;
+        update_gshadow(&mut gshadow, &name, previous.as_deref(), user, replaced);
+        write_database("/etc/gshadow", &gshadow)?;
+    }
+
+    Ok(name)
+}
+
+/// Adds user to every admin group that exists, returns names of the groups
+fn add_to_admin_groups(user: &HostUser) -> Result<Vec<String>> {
+    let mut groups = read_database("/etc/group")?;
+    let mut added = vec![];
+
+    for line in groups.iter_mut() {
+        let name = field(line, 0).unwrap_or_default().to_string();
+        if !ADMIN_GROUPS.contains(&name.as_str()) {
+            continue;
+        }
+
+        let members = field(line, 3).unwrap_or_default();
+        if !members.split(',').any(|x| x == user.name) {
+            let members = if members.is_empty() { user.name.clone() } else { format!("{},{}", members, user.name) };
+            let prefix: Vec<&str> = line.split(':').take(3).collect();
+            *line = format!("{}:{}", prefix.join(":"), members);
+        }
+
+        added.push(name);
+    }
+
+    write_database("/etc/group", &groups)?;
+
+    Ok(added)
+}
+
+/// Allows the user to use sudo without password
+fn write_sudoers(user: &HostUser) -> Result<()> {
+    let path = Path::new(SUDOERS_PATH);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {:?}", parent))?;
+    }
+
+    let mut file = fs::File::create(path)
+        .with_context(|| format!("failed to create {:?}", path))?;
+    writeln!(file, "{} ALL=(ALL:ALL) NOPASSWD: ALL", user.name)?;
+
+    // sudo ignores files writable by others
+    fs::set_permissions(path, fs::Permissions::from_mode(0o440))
+        .with_context(|| format!("failed to set permissions of {:?}", path))
+}
+
+/// Creates home directory owned by the user if it does not exist
+fn ensure_home(user: &HostUser) -> Result<()> {
+    let home = Path::new(&user.home);
+    if home.exists() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(home)
+        .with_context(|| format!("failed to create home directory {:?}", home))?;
+
+    std::os::unix::fs::chown(home, Some(user.uid), Some(user.gid))
+        .with_context(|| format!("failed to change owner of {:?}", home))
+}
+
+/// Creates user and group matching the host user with passwordless sudo, safe to call on every
+/// start
+pub fn setup_user(user: &HostUser, verbose: bool) -> Result<()> {
+    if user.name.is_empty() || user.name.contains(':') {
+        return Err(Error::msg(format!("invalid username '{}'", user.name)));
+    }
+
+    // root always exists
+    if user.uid == 0 {
+        return Ok(());
+    }
+
+    let shell = pick_shell(&user.shell);
+
+    let replaced = ensure_user(user, shell)?;
+    let group = ensure_group(user, &replaced)?;
+    let admin_groups = add_to_admin_groups(user)?;
+    write_sudoers(user)?;
+    ensure_home(user)?;
+
+    if verbose {
+        if !replaced.is_empty() {
+            println!("Replaced user {} with the same uid", replaced.join(", "));
+        }
+
+        println!("User {} ({}:{}) in group {} with shell {}", user.name, user.uid, user.gid, group, shell);
+
+        if !admin_groups.is_empty() {
+            println!("User {} added to {}", user.name, admin_groups.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash
+ubuntu:x:1000:1000:Ubuntu:/home/ubuntu:/bin/bash";
+
+    const SHADOW: &str = "root:*:19000:0:99999:7:::
+ubuntu:!:19000:0:99999:7:::";
+
+    const GROUP: &str = "root:x:0:
+adm:x:4:syslog,ubuntu
+sudo:x:27:ubuntu
+users:x:100:
+ubuntu:x:1000:";
+
+    const GSHADOW: &str = "root:*::
+sudo:*::ubuntu
+ubuntu:!::";
+
+    fn lines(data: &str) -> Vec<String> {
+        data.lines().map(|x| x.to_string()).collect()
+    }
+
+    fn host_user(uid: u32, gid: u32) -> HostUser {
+        HostUser {
+            name: "alice".into(),
+            uid,
+            gid,
+            home: "/home/alice".into(),
+            shell: "/bin/zsh".into(),
+        }
+    }
+
+    #[test]
+    fn user_owning_uid_is_replaced() {
+        let user = host_user(1000, 1000);
+
+        let mut passwd = lines(PASSWD);
+        let replaced = update_passwd(&mut passwd, &user, "/bin/bash");
+        assert_eq!(replaced, ["ubuntu"]);
+        assert_eq!(passwd, lines("root:x:0:0:root:/root:/bin/bash
+alice:x:1000:1000:alice:/home/alice:/bin/bash"));
+
+        let mut shadow = lines(SHADOW);
+        update_shadow(&mut shadow, &user, &replaced);
+        assert_eq!(shadow, lines("root:*:19000:0:99999:7:::
+alice:!::0:99999:7:::"));
+
+        // private group of the user is renamed with it
+        let mut group = lines(GROUP);
+        assert_eq!(update_group(&mut group, &user, &replaced), ("alice".into(), Some("ubuntu".into())));
+        assert_eq!(group, lines("root:x:0:
+adm:x:4:syslog,alice
+sudo:x:27:alice
+users:x:100:
+alice:x:1000:"));
+
+        let mut gshadow = lines(GSHADOW);
+        update_gshadow(&mut gshadow, "alice", Some("ubuntu"), &user, &replaced);
+        assert_eq!(gshadow, lines("root:*::
+sudo:*::alice
+alice:!::"));
+    }
+
+    #[test]
+    fn existing_user_is_updated() {
+        let user = host_user(1000, 1000);
+        let mut passwd = lines("root:x:0:0:root:/root:/bin/bash
+alice:x:1001:1001::/home/old:/bin/sh");
+
+        assert!(update_passwd(&mut passwd, &user, "/bin/zsh").is_empty());
+        assert_eq!(passwd[1], "alice:x:1000:1000:alice:/home/alice:/bin/zsh");
+
+        // updating again changes nothing
+        let previous = passwd.clone();
+        update_passwd(&mut passwd, &user, "/bin/zsh");
+        assert_eq!(passwd, previous);
+    }
+
+    #[test]
+    fn user_with_same_name_and_user_owning_uid_are_merged() {
+        let user = host_user(1000, 1000);
+        let mut passwd = lines(&format!("{}
+alice:x:1001:1001::/home/alice:/bin/sh", PASSWD));
+
+        assert_eq!(update_passwd(&mut passwd, &user, "/bin/sh"), ["ubuntu"]);
+        assert_eq!(passwd, lines("root:x:0:0:root:/root:/bin/bash
+alice:x:1000:1000:alice:/home/alice:/bin/sh"));
+    }
+
+    #[test]
+    fn new_user_is_appended() {
+        let user = host_user(1001, 1001);
+        let mut passwd = lines(PASSWD);
+
+        assert!(update_passwd(&mut passwd, &user, "/bin/sh").is_empty());
+        assert_eq!(passwd.len(), 3);
+        assert_eq!(passwd[2], "alice:x:1001:1001:alice:/home/alice:/bin/sh");
+
+        let mut shadow = lines(SHADOW);
+        update_shadow(&mut shadow, &user, &[]);
+        assert_eq!(shadow.len(), 3);
+    }
+
+    #[test]
+    fn shared_group_owning_gid_is_kept() {
+        let user = host_user(1000, 100);
+        let mut group = lines(GROUP);
+
+        assert_eq!(update_group(&mut group, &user, &["ubuntu".into()]), ("users".into(), None));
+        assert!(group.contains(&"ubuntu:x:1000:".to_string()));
+    }
+
+    #[test]
+    fn group_is_added_when_gid_is_free() {
+        let user = host_user(1001, 1001);
+
+        let mut group = lines(GROUP);
+        assert_eq!(update_group(&mut group, &user, &[]), ("alice".into(), None));
+        assert_eq!(group.last().unwrap(), "alice:x:1001:");
+
+        // name is taken by a group with different gid
+        let mut group = lines("alice:x:2000:");
+        assert_eq!(update_group(&mut group, &user, &[]), ("alice-host".into(), None));
+        assert_eq!(group, lines("alice:x:2000:
+alice-host:x:1001:"));
+
+        let mut gshadow = lines(GSHADOW);
+        update_gshadow(&mut gshadow, "alice-host", None, &user, &[]);
+        assert_eq!(gshadow.last().unwrap(), "alice-host:!::");
+    }
+}
@@ -5,8 +5,24 @@ use crate::manager::ContainerManager;
 
 use super::commands;
 
+/// Returns manager of the container, only available to init as the environment is reset by sudo
+fn get_manager() -> Result<ContainerManager> {
+    // NOTE using env var set on creation, reading label from within a container seems like pain
+    let manager_used = std::env::var("manager_used")
+        .with_context(|| "environment variable 'manager_used' is not defined, this container was not managed by legumemanager")?;
+
+    ContainerManager::from_str(&manager_used)
+        .context(format!("unsupported container manager '{}' used for container", &manager_used))
+}
+
 pub fn main() -> Result<()> {
-    // TODO if symlinked to distrobox-host-exec run host-exec directly
+    // when linked as host-exec or xdg-open the arguments belong to the command on the host
+    let argv: Vec<String> = std::env::args().collect();
+    if let Some(command) = commands::multicall_command(&argv) {
+        let rc = commands::host_exec(&command)?;
+        std::process::exit(rc);
+    }
+
     let mut args = cli::Cli::parse();
 
     // if quiet stay quiet
@@ -14,14 +30,11 @@ pub fn main() -> Result<()> {
         args.verbose = 0;
     }
 
-    // NOTE using env var set on creation, reading label from within a container seems like pain
-    let manager_used = std::env::var("manager_used")
-        .with_context(|| "environment variable 'manager_used' is not defined, this container was not managed by legumemanager")?;
-    let manager = ContainerManager::from_str(&manager_used)
-        .context(format!("unsupported container manager '{}' used for container", &manager_used))?;
-
     match &args.cmd {
-        CliCommands::Init => commands::cmd_init(&args, &manager),
+        CliCommands::Init(cmd_args) => commands::cmd_init(&args, &get_manager()?, cmd_args.clone()),
+        // executed by the user through sudo which resets the environment, so they cannot use it
+        CliCommands::HostExec(cmd_args) => commands::cmd_host_exec(&args, cmd_args.clone()),
+        CliCommands::CommandNotFound(cmd_args) => commands::cmd_command_not_found(&args, cmd_args.clone()),
     }
 }
 
@@ -1,4 +1,4 @@
-/// Contains cli interface when running on host operating system (not container)
+//! Contains cli interface when running on host operating system (not container)
 
 mod cli;
 mod commands;
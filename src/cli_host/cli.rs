@@ -2,6 +2,7 @@ use clap::{Parser, Subcommand, Args, ArgAction};
 use std::path::PathBuf;
 
 pub use crate::manager::ContainerManager;
+use super::commands::host_daemon::policy::HostExecPolicyKind;
 
 /// Podman wrapper for managing pet containers, get VM like experience using containers
 #[derive(Parser, Debug)]
@@ -19,6 +20,10 @@ pub struct Cli {
     #[arg(long)]
     pub dry_run: bool,
 
+    /// Allow using a container not created by legumemanager and remember it as adopted
+    #[arg(long)]
+    pub adopt: bool,
+
     /// Run container manager as root (uses sudo or doas)
     #[arg(long)]
     pub root: bool,
@@ -27,6 +32,11 @@ pub struct Cli {
     #[arg(long, value_enum)]
     pub manager: Option<ContainerManager>,
 
+    /// Directory where legumemanager keeps its data on host (defaults to legumemanager inside
+    /// the XDG data directory)
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+
     #[command(subcommand)]
     pub cmd: CliCommands,
 }
@@ -88,29 +98,26 @@ pub enum CliCommands {
 
     /// Push files to a container
     #[command(arg_required_else_help = true)]
-    Push {
-        /// Name of the container
-        container_name: String,
+    Push(CmdPushArgs),
 
-        /// Source on host
-        source: PathBuf,
+    /// Fetch files from a container
+    #[command(arg_required_else_help = true)]
+    Pull(CmdPullArgs),
 
-        /// Destination in the container
-        destination: PathBuf,
-    },
+    /// Change hostname of a container, applied on the next start
+    #[command(arg_required_else_help = true)]
+    SetHostname(CmdSetHostnameArgs),
 
-    /// Fetch files from a container
+    /// Show mounts init would do inside a running container without changing anything
     #[command(arg_required_else_help = true)]
-    Pull {
-        /// Name of the container
-        container_name: String,
+    DebugInit(CmdDebugInitArgs),
 
-        /// Source on host
-        source: PathBuf,
+    /// Show output of a container with init steps formatted
+    #[command(arg_required_else_help = true)]
+    Logs(CmdLogsArgs),
 
-        /// Destination in the container
-        destination: PathBuf,
-    },
+    /// Execute commands on the host requested by 'host-exec' inside containers
+    HostDaemon(CmdHostDaemonArgs),
 }
 
 #[derive(Args, Debug, Clone)]
@@ -144,6 +151,43 @@ pub struct CmdCreateArgs {
     #[arg(short, long)]
     pub env: Vec<String>,
 
+    /// Mount host path at the same path inside the container on start (eg. '/nix' or '/nix:ro')
+    #[arg(long = "host-mount")]
+    pub host_mounts: Vec<String>,
+
+    /// Do not mount host path from the default mounts (eg. '/run/libvirt')
+    #[arg(long = "skip-host-mount")]
+    pub skip_host_mounts: Vec<String>,
+
+    /// Load mounts from JSON mount spec, --host-mount and --skip-host-mount are applied on top
+    #[arg(long)]
+    pub mount_spec: Option<PathBuf>,
+
+    /// Execute the command on the host when it is not found inside the container (eg. 'flatpak')
+    #[arg(long = "host-command")]
+    pub host_commands: Vec<String>,
+
+    /// Execute every command not found inside the container on the host, except denied ones
+    #[arg(long)]
+    pub all_host_commands: bool,
+
+    /// Never execute the command on the host when it is not found inside the container
+    #[arg(long = "deny-host-command")]
+    pub deny_host_commands: Vec<String>,
+
+    /// Which commands the container may execute on the host using host-exec
+    #[arg(long, value_enum, default_value_t)]
+    pub host_exec_policy: HostExecPolicyKind,
+
+    /// Executable the container may execute on the host without asking (eg. 'flatpak')
+    #[arg(long = "host-exec-allow")]
+    pub host_exec_allow: Vec<String>,
+
+    /// Mount session bus of the host so host-exec works without the host daemon on hosts running
+    /// flatpak-session-helper, only allowed with host-exec policy 'all' as it bypasses the policy
+    #[arg(long)]
+    pub session_bus: bool,
+
     /// Pass extra arguments verbatim to container manager
     #[arg(short = 'a', long = "extra-arg")]
     pub extra_args: Vec<String>,
@@ -181,6 +225,7 @@ pub struct CmdExecArgs {
     pub container_name: String,
 
     /// Command to execute
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     pub command: Vec<String>,
 
     /// Use login shell
@@ -203,14 +248,30 @@ pub struct CmdExecArgs {
 #[derive(Args, Debug, Clone)]
 pub struct CmdListArgs{
     /// Show only running containers
-    #[arg(long)]
+    #[arg(long, conflicts_with = "state")]
     pub running: bool,
+
+    /// Show only containers in this state (eg. 'running', 'exited')
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// Show only containers whose image contains this string
+    #[arg(long)]
+    pub image: Option<String>,
+
+    /// Output as JSON formatted string
+    #[arg(short, long)]
+    pub json: bool,
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct CmdStartArgs{
     /// Name of the container
     pub container_name: String,
+
+    /// Seconds to wait for the container to initialize
+    #[arg(short, long, default_value_t = 30)]
+    pub timeout: u64,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -218,9 +279,13 @@ pub struct CmdStopArgs {
     /// Name of the container
     pub container_name: String,
 
-    /// Forcefully stop the container, may corrupt data
+    /// Forcefully stop the container if it does not stop in time, may corrupt data
     #[arg(short, long)]
     pub force: bool,
+
+    /// Seconds to wait for the container to stop gracefully
+    #[arg(short, long, default_value_t = 30)]
+    pub timeout: u64,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -231,5 +296,68 @@ pub struct CmdDestroyArgs {
     /// Do not ask for confirmation
     #[arg(long)]
     pub force: bool,
+
+    /// Delete the container home without asking, only applies to homes made using --home-prefix
+    #[arg(long)]
+    pub remove_home: bool,
 }
 
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdPushArgs {
+    /// Name of the container
+    pub container_name: String,
+
+    /// Source file or directory on host
+    pub source: PathBuf,
+
+    /// Destination path in the container, the copy is placed exactly there
+    pub destination: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdPullArgs {
+    /// Name of the container
+    pub container_name: String,
+
+    /// Source file or directory in the container
+    pub source: String,
+
+    /// Destination on host, if it is an existing directory the copy is placed inside it
+    pub destination: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdSetHostnameArgs {
+    /// Name of the container
+    pub container_name: String,
+
+    /// New hostname
+    pub hostname: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdDebugInitArgs {
+    /// Name of the container
+    pub container_name: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdLogsArgs {
+    /// Name of the container
+    pub container_name: String,
+
+    /// Keep printing new output until the container stops
+    #[arg(short, long)]
+    pub follow: bool,
+
+    /// Show only init steps
+    #[arg(long)]
+    pub init: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CmdHostDaemonArgs {
+    /// Serve only these containers (by default serves all)
+    pub containers: Vec<String>,
+}
@@ -1,6 +1,30 @@
 pub mod create;
 pub mod shell;
+pub mod exec;
+pub mod list;
+pub mod start;
+pub mod stop;
+pub mod destroy;
+pub mod push;
+pub mod pull;
+pub mod set_hostname;
+pub mod debug_init;
+pub mod logs;
+pub mod host_daemon;
 
 pub use create::cmd_create;
 pub use shell::cmd_shell;
+pub use exec::cmd_exec;
+pub use list::cmd_list;
+pub use start::cmd_start;
+pub use stop::cmd_stop;
+pub use destroy::cmd_destroy;
+pub use push::cmd_push;
+pub use pull::cmd_pull;
+pub use set_hostname::cmd_set_hostname;
+pub use debug_init::cmd_debug_init;
+pub use logs::cmd_logs;
+pub use host_daemon::cmd_host_daemon;
 
+#[cfg(test)]
+mod tests;
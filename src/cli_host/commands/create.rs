@@ -1,80 +1,126 @@
 //! Module that contains create command
 
-use std::process::Command;
 use std::path::Path;
 use super::super::util as host_util;
-use crate::{env_vars, util, VERSION, VERSION_STR};
-use crate::cli_host::cli::{Cli, CmdCreateArgs, ContainerManager};
+use super::host_daemon::policy;
+use crate::backend::{ContainerBackend, CreateOptions};
+use crate::command_fallback::{CommandFallback, COMMAND_FALLBACK_FILE};
+use crate::host_exec::{CONTAINER_SESSION_BUS, CONTAINER_SOCKET_DIR};
+use crate::mount_spec::{HostMount, MountSpec, HOST_ROOT, MOUNT_SPEC_FILE};
+use crate::{env_vars, CONTAINER_SCHEMA, VERSION, VERSION_STR};
+use crate::cli_host::cli::{Cli, CmdCreateArgs};
 use crate::{Error, Result, Context};
 
-fn generate_create_command(args: &Cli, cmd_args: &CmdCreateArgs) -> Result<Vec<String>> {
-    let mut cmd: Vec<String> = vec![];
+/// Signal used to stop containers with init system
+pub const INIT_STOP_SIGNAL: &str = "SIGRTMIN+3";
 
+fn generate_create_options(args: &Cli, cmd_args: &CmdCreateArgs) -> Result<CreateOptions> {
     let home = cmd_args.home.as_ref().unwrap();
     let hostname = cmd_args.hostname.as_ref().unwrap();
     let manager = args.manager.unwrap();
+    let username = users::get_current_username()
+        .with_context(|| "could not get host username")?
+        .into_string()
+        .map_err(|_| Error::msg("host username is not valid UTF-8"))?;
 
-    if hostname.len() > 255 {
-        return Err(Error::msg("hostname length is over 255 characters"));
-    }
+    host_util::validate_hostname(hostname)?;
 
     if cmd_args.container_name.len() > 64 {
         return Err(Error::msg("container name is over 64 characters"));
     }
 
-    cmd.extend(["create".into(),
-        "--name".into(), cmd_args.container_name.clone(),
-        "--hostname".into(), hostname.clone(),
-        // "--privileged".into(), // TODO find out what exactly it does
-        "--security-opt".into(), "label=disable".into(),
-        "--security-opt".into(), "apparmor=unconfined".into(),
-        "--user".into(), "root:root".into(),
-        "--network".into(), "host".into(),
-    ]);
+    let mut opts = CreateOptions {
+        name: cmd_args.container_name.clone(),
+        image: cmd_args.image.clone(),
+        hostname: hostname.clone(),
+        init: cmd_args.init,
+        root: args.root,
+        ..Default::default()
+    };
+
+    // information about the manager, kinda compatible with distrobox
+    opts.labels = vec![
+        ("manager".into(), "legumemanager".into()),
+        ("manager_schema".into(), CONTAINER_SCHEMA.to_string()),
+        ("manager_version".into(), VERSION_STR.into()),
+        ("manager_init".into(), cmd_args.init.to_string()),
+        // home made using prefix belongs to the container so it can be deleted with it
+        ("manager_home_prefix".into(), cmd_args.home_prefix.to_string()),
+        // labels cannot be changed from inside the container
+        (policy::POLICY_LABEL.into(), cmd_args.host_exec_policy.as_str().into()),
+    ];
+
+    if cmd_args.host_exec_allow.iter().any(|x| x.is_empty() || x.contains(',')) {
+        return Err(Error::msg("executables allowed to execute on host cannot be empty or contain ','"));
+    }
+
+    // the session bus executes anything on the host so any other policy could be bypassed
+    if cmd_args.session_bus && cmd_args.host_exec_policy != policy::HostExecPolicyKind::All {
+        return Err(Error::msg(format!("--session-bus cannot be used with host-exec policy '{}', only with 'all'", cmd_args.host_exec_policy.as_str())));
+    }
+
+    if !cmd_args.host_exec_allow.is_empty() {
+        opts.labels.push((policy::ALLOW_LABEL.into(), cmd_args.host_exec_allow.join(",")));
+    }
 
-    cmd.extend([
-        // information about the manager, kinda compatible with distrobox
-        "--label".into(), "manager=legumemanager".into(),
+    opts.env = vec![
         // TODO add these to env_vars
-        "--env".into(), format!("manager_used={}",  manager.get_executable_name()),
-        "--env".into(), format!("manager_version={}",  VERSION),
-        "--env".into(), format!("manager_version_str={}",  VERSION_STR),
-        "--env".into(), format!("container={}", manager.get_executable_name()),
+        format!("manager_used={}",  manager.get_executable_name()),
+        format!("manager_version={}",  VERSION),
+        format!("manager_version_str={}",  VERSION_STR),
+        format!("manager_init={}", cmd_args.init),
+
+        // init creates matching user inside the container
+        format!("manager_user={}", username),
+        format!("manager_uid={}", users::get_current_uid()),
+        format!("manager_gid={}", users::get_current_gid()),
+        format!("manager_user_shell={}", std::env::var("SHELL").unwrap_or_default()),
+        format!("container={}", manager.get_executable_name()),
 
         // im adding /bin/sh as default shell but will override it later
-        "--env".into(), "SHELL=/bin/sh".into(),
-        "--env".into(), format!("HOME={}", home),
+        "SHELL=/bin/sh".into(),
+        format!("HOME={}", home),
 
         // use host terminfo as fallback, useful for modern terminals like kitty
-        "--env".into(), "TERMINFO_DIRS=/usr/share/terminfo:/run/host/usr/share/terminfo:/run/host/etc/terminfo:/run/host/usr/lib/terminfo".into(),
-        "--volume".into(), "/etc/terminfo:/run/host/etc/terminfo:ro".into(),
-        "--volume".into(), "/usr/lib/terminfo:/run/host/usr/lib/terminfo:ro".into(),
-        "--volume".into(), "/usr/share/terminfo:/run/host/usr/share/terminfo:ro".into(),
-
-        // TODO maybe move these into init so that it can be done depending on the container
-        // manager
-        // i do not know if all of these are needed but i guess wont hurt?
-        "--mount".into(), "type=tmpfs,destination=/tmp".into(),
-        "--mount".into(), "type=tmpfs,destination=/var/lib/journal".into(),
-        "--mount".into(), "type=tmpfs,destination=/run".into(),
-        "--mount".into(), "type=tmpfs,destination=/run/lock".into(),
-    ]);
-
-    // for debian, see if /lib/terminfo exists
-    if Path::new("/lib/terminfo").exists() {
-        cmd.extend(["--volume".into(), "/run/host/lib/terminfo:ro".into()]);
-    }
-
-    // things for systemd
+        "TERMINFO_DIRS=/usr/share/terminfo:/run/host/usr/share/terminfo:/run/host/etc/terminfo:/run/host/usr/lib/terminfo".into(),
+    ];
+
+    // init mounts host paths from here, it is writable as the mounts made from it inherit its
+    // flags and the ones that should be read-only are remounted by init
+    opts.volumes = vec![
+        format!("/:{}:rslave", HOST_ROOT),
+    ];
+
+    // TODO maybe move these into init so that it can be done depending on the container
+    // manager
+    // i do not know if all of these are needed but i guess wont hurt?
+    opts.mounts = vec![
+        "type=tmpfs,destination=/tmp".into(),
+        "type=tmpfs,destination=/var/lib/journal".into(),
+        "type=tmpfs,destination=/run".into(),
+        "type=tmpfs,destination=/run/lock".into(),
+    ];
+
+    // host daemon creates the socket inside it
+    let host_exec_dir = host_util::get_host_exec_dir(args, &cmd_args.container_name);
+    if !args.dry_run {
+        std::fs::create_dir_all(&host_exec_dir)
+            .with_context(|| format!("failed to create directory {:?}", host_exec_dir))?;
+    }
+    opts.volumes.push(format!("{}:{}", host_exec_dir.display(), CONTAINER_SOCKET_DIR));
+
+    if cmd_args.session_bus {
+        let bus = std::env::var("XDG_RUNTIME_DIR").ok()
+            .map(|x| Path::new(&x).join("bus"))
+            .filter(|x| x.exists())
+            .ok_or_else(|| Error::msg("could not find session bus socket in XDG_RUNTIME_DIR"))?;
+
+        opts.volumes.push(format!("{}:{}", bus.display(), CONTAINER_SESSION_BUS));
+    }
+
+    // systemd expects this signal to shutdown cleanly
     if cmd_args.init {
-        match manager {
-            ContainerManager::Docker => {
-                cmd.push("--cgroupns".into());
-            },
-            ContainerManager::Podman => {
-                cmd.extend(["--stop-signal".into(), "SIGRTMIN+3".into()]);
-            },
-        }
+        opts.stop_signal = Some(INIT_STOP_SIGNAL.into());
     }
 
     // make RHEL subscriptions work
@@ -86,9 +132,7 @@ fn generate_create_command(args: &Cli, cmd_args: &CmdCreateArgs) -> Result<Vec<S
 
     for (host_path, container_path) in rhel_sub_files {
         if Path::new(host_path).exists() {
-            cmd.extend([
-                "--volume".into(), format!("{}:{}:ro", host_path, container_path)
-            ]);
+            opts.volumes.push(format!("{}:{}:ro", host_path, container_path));
         }
     }
 
@@ -99,53 +143,48 @@ fn generate_create_command(args: &Cli, cmd_args: &CmdCreateArgs) -> Result<Vec<S
     // let user_xdg_runtime_path = format!("/run/user/{}", user_id);
     //
     // if Path::new(&user_xdg_runtime_path).exists() && !cmd_args.init {
-    //     cmd.extend([
-    //         "--volume".into(), format!("{0}:{0}:rslave", user_xdg_runtime_path),
-    //     ])
+    //     opts.volumes.push(format!("{0}:{0}:rslave", user_xdg_runtime_path));
     // }
 
-    if manager == ContainerManager::Podman {
-        cmd.extend([
-           "--ulimit".into(), "host".into(),
-           "--annotation".into(), "run.oci.keep_original_groups=1".into(),
-        ]);
-
-        if cmd_args.init {
-            cmd.push("--systemd=always".into());
-        }
-
-        if !args.root {
-            cmd.extend([
-               "--userns".into(), "keep-id".into(),
-            ]);
-        }
-    }
-
     // add additional env values, i wont check for errors here i dont care
-    for i in &cmd_args.env {
-        cmd.extend([
-            "--env".into(), i.into(),
-        ]);
-    }
+    opts.env.extend(cmd_args.env.iter().cloned());
 
     // add additional flags
-    cmd.extend(cmd_args.extra_args.clone());
+    opts.extra_args = cmd_args.extra_args.clone();
 
     // im guessing this is the thing that gets called when the container starts
     // i want to support `podman start <container>` too for use with ansible
-    cmd.extend([
-        // execute legumemanager init on startup
-        "--entrypoint".into(), r#"["/lm", "init"]"#.into(),
-        cmd_args.image.clone().into(),
-    ]);
+    // execute legumemanager init on startup
+    opts.entrypoint = vec!["/lm".into(), "init".into()];
 
-    Ok(cmd)
+    Ok(opts)
 }
 
-pub fn cmd_create(args: &Cli, mut cmd_args: CmdCreateArgs) -> Result<()> {
+/// Generates mount spec from the file and the flags
+fn generate_mount_spec(cmd_args: &CmdCreateArgs) -> Result<MountSpec> {
+    let mut spec = match &cmd_args.mount_spec {
+        Some(path) => MountSpec::load(path)?,
+        None => MountSpec::default(),
+    };
+
+    for i in &cmd_args.host_mounts {
+        spec.add.push(HostMount::parse(i)?);
+    }
+
+    for i in &cmd_args.skip_host_mounts {
+        if !i.starts_with('/') {
+            return Err(Error::msg(format!("mount path '{}' has to be absolute", i)));
+        }
+
+        spec.remove.push(i.clone());
+    }
+
+    Ok(spec)
+}
+
+pub fn cmd_create(args: &Cli, backend: &dyn ContainerBackend, mut cmd_args: CmdCreateArgs) -> Result<()> {
     // check if container already exists
-    let state = host_util::get_container_state(args.manager.as_ref().unwrap(), &cmd_args.container_name)?;
-    if state.is_some() {
+    if backend.inspect(&cmd_args.container_name)?.is_some() {
         return Err(Error::msg(format!("container '{}' already exists", &cmd_args.container_name)));
     }
 
@@ -184,48 +223,43 @@ pub fn cmd_create(args: &Cli, mut cmd_args: CmdCreateArgs) -> Result<()> {
     if !home_path.exists() {
         // create the home path
         std::fs::create_dir(home_path)
-            .with_context(|| format!("cannot create home directory at '{}'", home_path.to_str().unwrap_or("NONE".into())))?;
+            .with_context(|| format!("cannot create home directory at '{}'", home_path.to_str().unwrap_or("NONE")))?;
     }
 
-    let output = generate_create_command(args, &cmd_args)
-        .with_context(|| "failed to generate podman create command")?;
+    let opts = generate_create_options(args, &cmd_args)
+        .with_context(|| "failed to generate create options")?;
 
-    if args.dry_run {
-        print!("{}", args.manager.unwrap().get_executable_name());
-        for arg in output {
-            print!(" {}", arg);
-        }
-        println!();
-        return Ok(());
-    }
+    let mount_spec = generate_mount_spec(&cmd_args)?;
 
-    if args.verbose >= 1 {
+    let command_fallback = CommandFallback {
+        allow_all: cmd_args.all_host_commands,
+        allow: cmd_args.host_commands.clone(),
+        deny: cmd_args.deny_host_commands.clone(),
+    };
+
+    if args.verbose >= 1 && !args.dry_run {
         println!("Creating container {}", &cmd_args.container_name);
     }
 
-    let command = Command::new(args.manager.unwrap().get_executable_name())
-        .args(output)
-        .output()
-        .with_context(|| format!("failed to execute manager '{:?}'", args.manager.unwrap()))?;
+    backend.create(&opts)?;
 
-    if command.status.success() {
-        // push executable into the container
-        host_util::push_executable_into_container(args.manager.as_ref().unwrap(), &cmd_args.container_name, "/lm".into())
-            .with_context(|| format!("Failed to push executable into container '{}'", cmd_args.container_name))?;
+    // push executable into the container
+    host_util::push_executable_into_container(backend, &cmd_args.container_name, "/lm".into())
+        .with_context(|| format!("Failed to push executable into container '{}'", cmd_args.container_name))?;
 
-        if args.verbose >= 1 {
-            println!("Container successfully created");
-        }
+    if !mount_spec.is_default() {
+        host_util::push_json_file(backend, &cmd_args.container_name, MOUNT_SPEC_FILE, &mount_spec)
+            .with_context(|| format!("Failed to push mount spec into container '{}'", cmd_args.container_name))?;
+    }
 
-        Ok(())
-    } else {
-        // TODO add stdout and stderr together it will be nicer looking
-        Err(Error::msg(
-            format!("Container creation failed:\nStdout: {}\n\nStderr: {}",
-                String::from_utf8(command.stdout).unwrap(),
-                String::from_utf8(command.stderr).unwrap()
-            )
-        ))
+    if !command_fallback.is_disabled() {
+        host_util::push_json_file(backend, &cmd_args.container_name, COMMAND_FALLBACK_FILE, &command_fallback)
+            .with_context(|| format!("Failed to push command fallback rules into container '{}'", cmd_args.container_name))?;
     }
-}
 
+    if args.verbose >= 1 && !args.dry_run {
+        println!("Container successfully created");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+//! Module contains debug-init command
+
+use crate::backend::{ContainerBackend, ExecOptions};
+use crate::cli_host::util;
+use crate::{Error, Result};
+use super::super::cli::{Cli, CmdDebugInitArgs};
+
+pub fn cmd_debug_init(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdDebugInitArgs) -> Result<()> {
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    // the plan is computed from the mount table of the running container
+    if !info.is_running() && !args.dry_run {
+        return Err(Error::msg(format!("container '{}' is not running, start it first", &cmd_args.container_name)));
+    }
+
+    let rc = backend.exec(&ExecOptions {
+        container_name: cmd_args.container_name.clone(),
+        user: Some("root".into()),
+        command: vec!["/lm".into(), "init".into(), "--plan".into()],
+        ..Default::default()
+    })?;
+
+    if rc != 0 {
+        return Err(Error::msg(format!("init plan failed with exit code {}", rc)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+//! Module contains destroy command
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::backend::{ContainerBackend, ContainerInfo};
+use crate::manager::ContainerManager;
+use crate::cli_host::util;
+use crate::{Result, Context, Error};
+use super::super::cli::{Cli, CmdDestroyArgs};
+use super::stop::stop_container;
+
+/// Returns home of the container only if it was made for it using --home-prefix and is safe to
+/// delete
+fn get_owned_home(info: &ContainerInfo) -> Result<Option<PathBuf>> {
+    if !info.label_is_true("manager_home_prefix") {
+        return Ok(None);
+    }
+
+    let home = PathBuf::from(util::get_container_home(info)?);
+    if !home.is_dir() {
+        return Ok(None);
+    }
+
+    // never ever touch the real home or anything above it
+    let home = home.canonicalize()
+        .with_context(|| format!("failed to resolve path {:?}", home))?;
+    let host_home = dirs::home_dir()
+        .context("failed to get home directory")?
+        .canonicalize()
+        .with_context(|| "failed to resolve host home directory")?;
+
+    if host_home.starts_with(&home) {
+        return Ok(None);
+    }
+
+    Ok(Some(home))
+}
+
+fn remove_home(manager: ContainerManager, home: &Path) -> Result<()> {
+    // files made by container root are owned by subordinate ids in rootless podman
+    if std::fs::remove_dir_all(home).is_err() && manager == ContainerManager::Podman {
+        let status = Command::new(manager.get_executable_name())
+            .args(["unshare", "rm", "-rf", "--"])
+            .arg(home)
+            .status()
+            .with_context(|| "failed to execute 'podman unshare'")?;
+
+        if status.success() {
+            return Ok(());
+        }
+    }
+
+    if home.exists() {
+        return Err(Error::msg(format!("failed to delete home directory {:?}", home)));
+    }
+
+    Ok(())
+}
+
+pub fn cmd_destroy(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdDestroyArgs) -> Result<()> {
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    let home = get_owned_home(&info)?;
+
+    if !cmd_args.force && !args.dry_run
+        && !util::confirm(&format!("Are you sure you want to destroy container '{}'?", &cmd_args.container_name))? {
+        return Err(Error::msg("aborted by user"));
+    }
+
+    // home is only deleted when asked explicitly, force alone is not enough
+    let delete_home = match &home {
+        Some(_) if cmd_args.remove_home => true,
+        Some(home) if !cmd_args.force && !args.dry_run => util::confirm(&format!("Delete container home at {:?} as well?", home))?,
+        _ => false,
+    };
+
+    if info.is_running() {
+        stop_container(args, backend, &info, true, util::INIT_TIMEOUT)?;
+    }
+
+    if args.dry_run {
+        backend.remove(&cmd_args.container_name)?;
+        if let (Some(home), true) = (&home, delete_home) {
+            println!("rm -rf {:?}", home);
+        }
+
+        return Ok(());
+    }
+
+    backend.remove(&cmd_args.container_name)?;
+    util::forget_adopted_container(args, &cmd_args.container_name)?;
+
+    let host_exec_dir = util::get_host_exec_dir(args, &cmd_args.container_name);
+    if host_exec_dir.exists() {
+        std::fs::remove_dir_all(&host_exec_dir)
+            .with_context(|| format!("failed to delete {:?}", host_exec_dir))?;
+    }
+
+    if args.verbose >= 1 {
+        println!("Container {} destroyed", &cmd_args.container_name);
+    }
+
+    if let (Some(home), true) = (&home, delete_home) {
+        remove_home(backend.manager(), home)?;
+
+        if args.verbose >= 1 {
+            println!("Deleted home directory {:?}", home);
+        }
+    }
+
+    Ok(())
+}
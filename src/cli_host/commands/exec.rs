@@ -0,0 +1,59 @@
+//! Module contains exec command
+
+use std::io::IsTerminal;
+use std::process::exit;
+use crate::backend::{ContainerBackend, ExecOptions};
+use crate::cli_host::util;
+use crate::{Result, Context, Error};
+use super::super::cli::{Cli, CmdExecArgs};
+
+pub(super) fn generate_exec_options(cmd_args: &CmdExecArgs, home: &str) -> Result<ExecOptions> {
+    // allocate tty only if there is one to pass through
+    let tty = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+
+    let mut opts = util::generate_exec_options(
+        &cmd_args.container_name,
+        cmd_args.workdir.as_ref().unwrap(),
+        home,
+        &cmd_args.env,
+        &cmd_args.extra_args,
+        tty,
+    );
+
+    let user_id = users::get_current_username().with_context(|| "could not get host username")?.into_string().unwrap();
+
+    opts.command = util::sudo_command(&opts, user_id);
+
+    // run the command through login shell of the user
+    if cmd_args.login {
+        opts.command.push("-i".into());
+    }
+
+    opts.command.push("--".into());
+    opts.command.extend(cmd_args.command.clone());
+
+    Ok(opts)
+}
+
+pub fn cmd_exec(args: &Cli, backend: &dyn ContainerBackend, mut cmd_args: CmdExecArgs) -> Result<()> {
+    if cmd_args.command.is_empty() {
+        return Err(Error::msg("no command to execute"));
+    }
+
+    // check if container already exists
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    let home = util::get_container_home(&info)?;
+
+    // default workdir to home
+    if cmd_args.workdir.is_none() {
+        cmd_args.workdir = Some(home.clone());
+    }
+
+    let opts = generate_exec_options(&cmd_args, &home)?;
+
+    // exit with same exit code
+    exit(backend.exec(&opts)?);
+}
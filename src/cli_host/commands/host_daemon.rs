@@ -0,0 +1,185 @@
+//! Module contains host-daemon command, it executes commands requested by host-exec from inside
+//! of containers
+
+mod audit;
+pub mod policy;
+mod session;
+
+use std::collections::HashMap;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use crate::backend::ContainerBackend;
+use crate::cli_host::util;
+use crate::host_exec::SOCKET_NAME;
+use crate::{Context, Error, Result};
+use super::super::cli::{Cli, CmdHostDaemonArgs};
+use audit::AuditLog;
+use policy::HostExecPolicy;
+
+/// How often new containers are looked for
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Audit log inside the data directory, it is not mounted into any container
+const AUDIT_LOG_FILE: &str = "host-exec-audit.log";
+
+/// Container served by the daemon
+struct Served {
+    name: String,
+    policy: HostExecPolicy,
+    audit: Arc<AuditLog>,
+    verbose: u8,
+}
+
+fn serve_connection(served: &Served, mut stream: UnixStream) {
+    let mut request = match session::read_request(&mut stream) {
+        Ok(Some(x)) => x,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("{}: invalid request: {:#}", served.name, err);
+            return;
+        },
+    };
+
+    let time = SystemTime::now();
+    served.policy.filter_env(&mut request);
+    let allowed = served.policy.check(&served.name, &request);
+
+    if served.verbose >= 1 {
+        let status = if allowed { "" } else { " (denied)" };
+        println!("{}: {}{}", served.name, request.argv.join(" "), status);
+    }
+
+    let exit_code = if allowed {
+        match session::run(stream, &request) {
+            Ok(code) => Some(code),
+            Err(err) => {
+                eprintln!("{}: {:#}", served.name, err);
+                None
+            },
+        }
+    } else {
+        let message = format!("host-exec: executing '{}' was denied by policy of the container", request.argv[0]);
+        if let Err(err) = session::deny(stream, &message) {
+            eprintln!("{}: {:#}", served.name, err);
+        }
+
+        None
+    };
+
+    if let (Some(code), true) = (exit_code, served.verbose >= 2) {
+        println!("{}: '{}' exited with {}", served.name, request.argv[0], code);
+    }
+
+    if let Err(err) = served.audit.record(time, &served.name, &request, allowed, exit_code) {
+        eprintln!("{}: {:#}", served.name, err);
+    }
+}
+
+fn listen(served: Served, listener: UnixListener) {
+    let served = Arc::new(served);
+
+    for stream in listener.incoming().filter_map(|x| x.ok()) {
+        let served = served.clone();
+        std::thread::spawn(move || serve_connection(&served, stream));
+    }
+}
+
+/// Binds the socket replacing a stale one, fails if another daemon is listening on it
+fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(Error::msg(format!("another host daemon is already listening on {:?}", path)));
+        }
+
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to delete stale socket {:?}", path))?;
+    }
+
+    UnixListener::bind(path)
+        .with_context(|| format!("failed to bind socket {:?}", path))
+}
+
+/// Returns names and socket paths of containers that should be served
+fn list_sockets(args: &Cli, containers: &[String]) -> Result<Vec<(String, PathBuf)>> {
+    let root = util::get_host_exec_root(args);
+
+    // containers created by older versions do not have the directory
+    let Ok(entries) = std::fs::read_dir(&root) else {
+        return Ok(vec![]);
+    };
+
+    Ok(entries
+        .filter_map(|x| x.ok())
+        .filter(|x| x.path().is_dir())
+        .filter_map(|x| x.file_name().into_string().ok())
+        .filter(|x| containers.is_empty() || containers.contains(x))
+        .map(|x| {
+            let path = root.join(&x).join(SOCKET_NAME);
+            (x, path)
+        })
+        .collect())
+}
+
+/// Reads policy of the container and binds its socket
+fn serve(backend: &dyn ContainerBackend, container_name: &str, path: &Path, audit: &Arc<AuditLog>, verbose: u8) -> Result<()> {
+    // container was removed without legumemanager
+    let Some(info) = backend.inspect(container_name)? else {
+        return Ok(());
+    };
+
+    let policy = HostExecPolicy::from_container(&info)?;
+    let listener = bind(path)?;
+
+    if verbose >= 1 {
+        println!("Listening for container {} (policy {})", container_name, policy.kind.as_str());
+    }
+
+    let served = Served {
+        name: container_name.into(),
+        policy,
+        audit: audit.clone(),
+        verbose,
+    };
+
+    std::thread::spawn(move || listen(served, listener));
+
+    Ok(())
+}
+
+pub fn cmd_host_daemon(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdHostDaemonArgs) -> Result<()> {
+    let audit_path = util::get_data_dir(args).join(AUDIT_LOG_FILE);
+    let audit = Arc::new(AuditLog::open(&audit_path)?);
+
+    if args.verbose >= 2 {
+        println!("Writing audit log to {:?}", audit_path);
+    }
+
+    // sockets that are served, they are bound again if deleted
+    let mut served: HashMap<String, PathBuf> = HashMap::new();
+    let mut first_scan = true;
+
+    loop {
+        served.retain(|_, path| path.exists());
+
+        for (container_name, path) in list_sockets(args, &cmd_args.containers)? {
+            if served.contains_key(&container_name) {
+                continue;
+            }
+
+            match serve(backend, &container_name, &path, &audit, args.verbose) {
+                Ok(()) => {},
+                // do not serve anything if the daemon is already running
+                Err(err) if first_scan => return Err(err),
+                Err(err) => eprintln!("{}: {:#}", container_name, err),
+            }
+
+            // not retried until the socket is deleted
+            served.insert(container_name, path);
+        }
+
+        first_scan = false;
+        std::thread::sleep(SCAN_INTERVAL);
+    }
+}
@@ -0,0 +1,96 @@
+//! Audit log of host-exec requests, every request is a JSON object on its own line
+
+use crate::host_exec::HostExecRequest;
+use crate::util::format_timestamp;
+use crate::{Context, Result};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[derive(Serialize, Debug)]
+struct AuditRecord<'a> {
+    /// Time the request was received
+    time: String,
+    container: &'a str,
+    argv: &'a [String],
+    cwd: &'a str,
+    allowed: bool,
+
+    /// Not set if the command could not be executed
+    exit_code: Option<i32>,
+}
+
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Opens the log for appending, it is created if it does not exist
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {:?}", parent))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open audit log {:?}", path))?;
+
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Appends the request, the environment is left out as it often contains secrets
+    pub fn record(&self, time: SystemTime, container_name: &str, request: &HostExecRequest, allowed: bool, exit_code: Option<i32>) -> Result<()> {
+        let record = AuditRecord {
+            time: format_timestamp(time),
+            container: container_name,
+            argv: &request.argv,
+            cwd: &request.cwd,
+            allowed,
+            exit_code,
+        };
+
+        let line = serde_json::to_string(&record)? + "\n";
+
+        // whole line at once so records from multiple requests do not interleave
+        let mut file = self.file.lock().unwrap_or_else(|x| x.into_inner());
+        file.write_all(line.as_bytes())
+            .with_context(|| "failed to write audit log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn record_format() {
+        let path = std::env::temp_dir().join(format!("lm-audit-test-{}/audit.log", std::process::id()));
+        let log = AuditLog::open(&path).unwrap();
+
+        let request = HostExecRequest {
+            argv: vec!["xdg-open".into(), "https://example.com".into()],
+            env: vec![("GITHUB_TOKEN".into(), "secret".into())],
+            cwd: "/home/test".into(),
+            tty: None,
+        };
+
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        log.record(time, "pet", &request, true, Some(0)).unwrap();
+        log.record(time, "pet", &request, false, None).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(content, concat!(
+            r#"{"time":"2023-11-14T22:13:20.123Z","container":"pet","argv":["xdg-open","https://example.com"],"cwd":"/home/test","allowed":true,"exit_code":0}"#, "\n",
+            r#"{"time":"2023-11-14T22:13:20.123Z","container":"pet","argv":["xdg-open","https://example.com"],"cwd":"/home/test","allowed":false,"exit_code":null}"#, "\n",
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+//! Per container policy deciding which host-exec requests are executed, it is stored in labels of
+//! the container so it cannot be changed from inside of it
+
+use crate::backend::ContainerInfo;
+use crate::cli_host::util;
+use crate::host_exec::HostExecRequest;
+use crate::{Error, Result};
+use std::io::IsTerminal;
+use std::sync::Mutex;
+
+pub const POLICY_LABEL: &str = "manager_host_exec_policy";
+pub const ALLOW_LABEL: &str = "manager_host_exec_allow";
+
+/// Variables passed to the command when only some executables are allowed, anything else like
+/// `LD_PRELOAD` could change what an allowed executable does
+const RESTRICTED_ENV: [&str; 5] = ["TERM", "COLORTERM", "LANG", "LANGUAGE", "NO_COLOR"];
+
+/// Requests come from multiple threads but only one prompt can be answered at a time
+static PROMPT_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum HostExecPolicyKind {
+    /// Execute every command
+    #[default]
+    All,
+
+    /// Execute only allowed executables
+    Allowlist,
+
+    /// Ask in the terminal of the host daemon before executing executables that are not allowed
+    Prompt,
+}
+
+impl HostExecPolicyKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Self::All => "all",
+            Self::Allowlist => "allowlist",
+            Self::Prompt => "prompt",
+        }
+    }
+
+    pub fn from_str(name: &str) -> Option<Self> {
+        match name {
+            "all" => Some(Self::All),
+            "allowlist" => Some(Self::Allowlist),
+            "prompt" => Some(Self::Prompt),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HostExecPolicy {
+    pub kind: HostExecPolicyKind,
+
+    /// Executables as they are requested, names do not match paths
+    pub allow: Vec<String>,
+}
+
+impl HostExecPolicy {
+    /// Reads the policy from labels of the container, containers made before policies existed
+    /// allow everything
+    pub fn from_container(info: &ContainerInfo) -> Result<Self> {
+        let kind = match info.label(POLICY_LABEL) {
+            Some(x) => HostExecPolicyKind::from_str(x)
+                .ok_or_else(|| Error::msg(format!("invalid host-exec policy '{}' of container '{}'", x, info.name)))?,
+            None => HostExecPolicyKind::All,
+        };
+
+        let allow = info.label(ALLOW_LABEL)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect();
+
+        Ok(Self { kind, allow })
+    }
+
+    fn is_allowed(&self, executable: &str) -> bool {
+        self.allow.iter().any(|x| x == executable)
+    }
+
+    /// Removes variables the command should not get, only policy allowing every command passes
+    /// the whole environment
+    pub fn filter_env(&self, request: &mut HostExecRequest) {
+        if self.kind != HostExecPolicyKind::All {
+            request.env.retain(|(key, _)| RESTRICTED_ENV.contains(&key.as_str()) || key.starts_with("LC_"));
+        }
+    }
+
+    /// Decides if the request should be executed
+    pub fn check(&self, container_name: &str, request: &HostExecRequest) -> bool {
+        match self.kind {
+            HostExecPolicyKind::All => true,
+            HostExecPolicyKind::Allowlist => self.is_allowed(&request.argv[0]),
+            HostExecPolicyKind::Prompt => self.is_allowed(&request.argv[0]) || prompt(container_name, request),
+        }
+    }
+}
+
+/// Asks the user, the request is denied if the daemon is not running in a terminal
+fn prompt(container_name: &str, request: &HostExecRequest) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+
+    let _lock = PROMPT_LOCK.lock().unwrap_or_else(|x| x.into_inner());
+
+    util::confirm(&format!(
+        "Container '{}' wants to execute '{}' in {:?}, allow?",
+        container_name, request.argv.join(" "), request.cwd,
+    )).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(env: &[(&str, &str)]) -> HostExecRequest {
+        HostExecRequest {
+            argv: vec!["flatpak".into()],
+            env: env.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            cwd: "/".into(),
+            tty: None,
+        }
+    }
+
+    #[test]
+    fn env_is_filtered_unless_everything_is_allowed() {
+        let env = [("TERM", "xterm"), ("LC_ALL", "C"), ("LD_PRELOAD", "/tmp/evil.so"), ("GIT_SSH_COMMAND", "sh")];
+
+        let mut policy = HostExecPolicy { kind: HostExecPolicyKind::All, allow: vec!["flatpak".into()] };
+        let mut all = request(&env);
+        policy.filter_env(&mut all);
+        assert_eq!(all.env.len(), env.len());
+
+        for kind in [HostExecPolicyKind::Allowlist, HostExecPolicyKind::Prompt] {
+            policy.kind = kind;
+            let mut restricted = request(&env);
+            policy.filter_env(&mut restricted);
+
+            let keys: Vec<&str> = restricted.env.iter().map(|(key, _)| key.as_str()).collect();
+            assert_eq!(keys, ["TERM", "LC_ALL"]);
+        }
+    }
+
+    fn container(labels: serde_json::Value) -> ContainerInfo {
+        ContainerInfo::from_json(serde_json::json!({ "Name": "pet", "Config": { "Labels": labels } })).unwrap()
+    }
+
+    #[test]
+    fn policy_from_labels() {
+        let policy = HostExecPolicy::from_container(&container(serde_json::json!({
+            POLICY_LABEL: "allowlist",
+            ALLOW_LABEL: "flatpak,xdg-open,",
+        }))).unwrap();
+
+        assert_eq!(policy.kind, HostExecPolicyKind::Allowlist);
+        assert_eq!(policy.allow, ["flatpak", "xdg-open"]);
+
+        // containers made before policies existed
+        let policy = HostExecPolicy::from_container(&container(serde_json::json!({}))).unwrap();
+        assert_eq!(policy.kind, HostExecPolicyKind::All);
+        assert!(policy.allow.is_empty());
+
+        let err = HostExecPolicy::from_container(&container(serde_json::json!({ POLICY_LABEL: "some" }))).unwrap_err();
+        assert_eq!(err.to_string(), "invalid host-exec policy 'some' of container 'pet'");
+    }
+
+    #[test]
+    fn check() {
+        let mut policy = HostExecPolicy { kind: HostExecPolicyKind::All, allow: vec!["flatpak".into()] };
+        let mut other = request(&[]);
+        other.argv = vec!["rm".into(), "-rf".into(), "/".into()];
+
+        assert!(policy.check("pet", &request(&[])));
+        assert!(policy.check("pet", &other));
+
+        policy.kind = HostExecPolicyKind::Allowlist;
+        assert!(policy.check("pet", &request(&[])));
+        assert!(!policy.check("pet", &other));
+
+        // executable is compared as requested
+        other.argv = vec!["/usr/bin/flatpak".into()];
+        assert!(!policy.check("pet", &other));
+
+        // allowed executables are not prompted for
+        policy.kind = HostExecPolicyKind::Prompt;
+        assert!(policy.check("pet", &request(&[])));
+    }
+}
@@ -0,0 +1,314 @@
+//! Single host-exec connection, the command is executed with its stdio relayed over the connection
+
+use crate::host_exec::{decode_size, is_ignored_env, read_frame, write_frame, FrameKind, HostExecRequest};
+use crate::{Context, Error, Result};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+type SharedStream = Arc<Mutex<UnixStream>>;
+
+/// How often output threads check if the command has exited while there is no output
+const POLL_INTERVAL_MS: i32 = 100;
+
+fn send(stream: &SharedStream, kind: FrameKind, payload: &[u8]) -> std::io::Result<()> {
+    let mut stream = stream.lock().unwrap_or_else(|x| x.into_inner());
+    write_frame(&mut *stream, kind, payload)
+}
+
+/// Copies output into frames until it is closed or until the command exits and there is nothing
+/// left to read, processes started in background by the command may keep the output open
+fn forward_output(mut reader: impl Read + AsRawFd + Send + 'static, stream: SharedStream, kind: FrameKind, exited: Arc<AtomicBool>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 8192];
+        loop {
+            // checked before waiting so output written just before the exit is not lost
+            let was_exited = exited.load(Ordering::Relaxed);
+
+            let mut poll_fd = libc::pollfd { fd: reader.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+
+            // SAFETY: the pointer is valid for the duration of the call
+            match unsafe { libc::poll(&mut poll_fd, 1, POLL_INTERVAL_MS) } {
+                0 if was_exited => return,
+                0 => continue,
+                x if x < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => continue,
+                x if x < 0 => return,
+                _ => {},
+            }
+
+            // pseudo terminal fails with EIO once the command exits
+            match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => return,
+                Ok(read) => if send(&stream, kind, &buffer[..read]).is_err() {
+                    return;
+                },
+            }
+        }
+    })
+}
+
+fn set_window_size(fd: i32, rows: u16, columns: u16) {
+    let size = libc::winsize { ws_row: rows, ws_col: columns, ws_xpixel: 0, ws_ypixel: 0 };
+
+    // SAFETY: the fd is valid and ioctl only reads the struct
+    unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size) };
+}
+
+/// Opens pseudo terminal pair as (master, slave)
+fn open_pty(rows: u16, columns: u16) -> Result<(OwnedFd, OwnedFd)> {
+    let mut master = -1;
+    let mut slave = -1;
+    let size = libc::winsize { ws_row: rows, ws_col: columns, ws_xpixel: 0, ws_ypixel: 0 };
+
+    // SAFETY: all pointers are valid, name and termios are optional
+    if unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), &size) } != 0 {
+        return Err(Error::msg(format!("failed to open pseudo terminal: {}", std::io::Error::last_os_error())));
+    }
+
+    // SAFETY: openpty returned valid fds that are not owned by anything else
+    Ok(unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) })
+}
+
+fn build_command(request: &HostExecRequest) -> Command {
+    let mut command = Command::new(&request.argv[0]);
+    command.args(&request.argv[1..]);
+
+    for (key, value) in &request.env {
+        if !is_ignored_env(key) {
+            command.env(key, value);
+        }
+    }
+
+    // the container may have different paths
+    if Path::new(&request.cwd).is_dir() {
+        command.current_dir(&request.cwd);
+    } else if let Some(home) = dirs::home_dir() {
+        command.current_dir(home);
+    }
+
+    command
+}
+
+/// Where input from the client goes
+enum Input {
+    Pty(File),
+    Pipe(Option<std::process::ChildStdin>),
+}
+
+/// Spawns the command returning it with its input and threads forwarding its output, the threads
+/// stop once exited is set and the output is drained
+fn spawn(request: &HostExecRequest, stream: &SharedStream, exited: &Arc<AtomicBool>) -> Result<(Child, Input, Vec<JoinHandle<()>>)> {
+    let mut command = build_command(request);
+
+    if let Some((rows, columns)) = request.tty {
+        let (master, slave) = open_pty(rows, columns)?;
+
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+
+        // SAFETY: only async signal safe functions are called, the new session gets the
+        // terminal as its controlling terminal
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+
+        let child = command.spawn()
+            .with_context(|| format!("failed to execute '{}'", request.argv[0]))?;
+
+        let master = File::from(master);
+        let output = forward_output(master.try_clone()?, stream.clone(), FrameKind::Stdout, exited.clone());
+
+        Ok((child, Input::Pty(master), vec![output]))
+    } else {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to execute '{}'", request.argv[0]))?;
+
+        let stdout = forward_output(child.stdout.take().unwrap(), stream.clone(), FrameKind::Stdout, exited.clone());
+        let stderr = forward_output(child.stderr.take().unwrap(), stream.clone(), FrameKind::Stderr, exited.clone());
+        let stdin = child.stdin.take();
+
+        Ok((child, Input::Pipe(stdin), vec![stdout, stderr]))
+    }
+}
+
+/// Applies input frames until the client disconnects, the command is hung up on if it is still
+/// running by then
+fn forward_input(mut reader: UnixStream, mut input: Input, pid: u32, tty: bool, exited: Arc<AtomicBool>) {
+    while let Ok(Some((kind, data))) = read_frame(&mut reader) {
+        match (kind, &mut input) {
+            (FrameKind::Stdin, Input::Pty(master)) => { let _ = master.write_all(&data); },
+            (FrameKind::Stdin, Input::Pipe(stdin)) if data.is_empty() => { stdin.take(); },
+            (FrameKind::Stdin, Input::Pipe(Some(stdin))) => { let _ = stdin.write_all(&data); },
+            (FrameKind::Resize, Input::Pty(master)) => {
+                if let Some((rows, columns)) = decode_size(&data) {
+                    set_window_size(master.as_raw_fd(), rows, columns);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // pid could be reused already
+    if exited.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // the command runs in its own session with a terminal so the whole group is hung up on
+    let target = if tty { -(pid as i32) } else { pid as i32 };
+
+    // SAFETY: sending a signal has no memory safety implications, the process may be gone
+    unsafe { libc::kill(target, libc::SIGHUP) };
+}
+
+/// Reads the request from the connection, None if it was closed without sending anything like
+/// when another daemon checks if the socket is in use
+pub fn read_request(stream: &mut UnixStream) -> Result<Option<HostExecRequest>> {
+    let request = match read_frame(stream)? {
+        Some((FrameKind::Request, data)) => serde_json::from_slice::<HostExecRequest>(&data)
+            .with_context(|| "failed to parse request")?,
+        Some(_) => return Err(Error::msg("expected request as the first frame")),
+        None => return Ok(None),
+    };
+
+    if request.argv.is_empty() {
+        return Err(Error::msg("request has no command"));
+    }
+
+    Ok(Some(request))
+}
+
+/// Refuses the request, the exit code is the one shells use for commands that cannot be executed
+pub fn deny(mut stream: UnixStream, message: &str) -> Result<()> {
+    write_frame(&mut stream, FrameKind::Stderr, format!("{}\n", message).as_bytes())?;
+    write_frame(&mut stream, FrameKind::Exit, &126i32.to_be_bytes())?;
+
+    Ok(())
+}
+
+/// Executes the request relaying stdio over the connection, returns the exit code
+pub fn run(stream: UnixStream, request: &HostExecRequest) -> Result<i32> {
+    let reader = stream.try_clone()?;
+    let stream: SharedStream = Arc::new(Mutex::new(stream));
+
+    let exited = Arc::new(AtomicBool::new(false));
+    let (mut child, input, outputs) = match spawn(request, &stream, &exited) {
+        Ok(x) => x,
+        Err(err) => {
+            // same as shells do when command cannot be executed
+            let _ = send(&stream, FrameKind::Stderr, format!("{:#}\n", err).as_bytes());
+            let _ = send(&stream, FrameKind::Exit, &127i32.to_be_bytes());
+            return Err(err);
+        },
+    };
+
+    let pid = child.id();
+    let tty = request.tty.is_some();
+    {
+        let exited = exited.clone();
+        std::thread::spawn(move || forward_input(reader, input, pid, tty, exited));
+    }
+
+    let status = child.wait()?;
+    exited.store(true, Ordering::Relaxed);
+
+    // mimics the shell when killed by a signal
+    let code = status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0));
+
+    for output in outputs {
+        let _ = output.join();
+    }
+
+    send(&stream, FrameKind::Exit, &code.to_be_bytes())?;
+
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn request(script: &str, tty: Option<(u16, u16)>) -> HostExecRequest {
+        HostExecRequest {
+            argv: vec!["sh".into(), "-c".into(), script.into()],
+            env: vec![],
+            cwd: "/".into(),
+            tty,
+        }
+    }
+
+    /// Runs the request over a socket pair sending input first, returns stdout, stderr and the
+    /// exit code received by the client
+    fn exec(request: HostExecRequest, input: &[u8]) -> (String, String, i32) {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let daemon = std::thread::spawn(move || run(server, &request).unwrap());
+
+        if !input.is_empty() {
+            write_frame(&mut client, FrameKind::Stdin, input).unwrap();
+        }
+        write_frame(&mut client, FrameKind::Stdin, &[]).unwrap();
+
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let code = loop {
+            match read_frame(&mut client).unwrap() {
+                Some((FrameKind::Stdout, data)) => stdout.extend(data),
+                Some((FrameKind::Stderr, data)) => stderr.extend(data),
+                Some((FrameKind::Exit, data)) => break i32::from_be_bytes(data.try_into().unwrap()),
+                x => panic!("unexpected frame {:?}", x),
+            }
+        };
+
+        assert_eq!(daemon.join().unwrap(), code);
+
+        (String::from_utf8(stdout).unwrap(), String::from_utf8(stderr).unwrap(), code)
+    }
+
+    #[test]
+    fn pipes() {
+        let (stdout, stderr, code) = exec(request("cat; echo error >&2; exit 3", None), b"hello\n");
+
+        assert_eq!(stdout, "hello\n");
+        assert_eq!(stderr, "error\n");
+        assert_eq!(code, 3);
+    }
+
+    #[test]
+    fn pseudo_terminal() {
+        let (stdout, _, code) = exec(request("test -t 0 && test -t 1 && echo terminal; exit 4", Some((24, 80))), b"");
+
+        // terminal translates newlines
+        assert_eq!(stdout, "terminal\r\n");
+        assert_eq!(code, 4);
+    }
+
+    #[test]
+    fn background_process_keeps_output_open() {
+        for tty in [None, Some((24, 80))] {
+            let start = Instant::now();
+            let (stdout, _, code) = exec(request("sleep 5 & echo started", tty), b"");
+
+            assert!(start.elapsed() < Duration::from_secs(3), "waited for the background process");
+            assert!(stdout.starts_with("started"));
+            assert_eq!(code, 0);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Module contains list command
+
+use crate::backend::{ContainerBackend, ContainerInfo};
+use crate::cli_host::util;
+use crate::{Result, Context};
+use super::super::cli::{Cli, CmdListArgs};
+use serde::Serialize;
+
+/// Information about a single container shown by list command
+#[derive(Serialize, Debug)]
+struct ContainerEntry {
+    name: String,
+    image: String,
+    state: String,
+    hostname: String,
+    home: Option<String>,
+    init: bool,
+    version: Option<String>,
+}
+
+fn get_container_entry(info: &ContainerInfo) -> ContainerEntry {
+    // containers made by older versions do not have the label
+    let version = info.label("manager_version")
+        .or_else(|| info.env_var("manager_version_str"))
+        .map(|x| x.to_string());
+
+    ContainerEntry {
+        name: info.name.clone(),
+        image: info.config.image.clone(),
+        state: info.state.status.clone(),
+        hostname: info.config.hostname.clone(),
+        home: info.env_var("HOME").map(|x| x.to_string()),
+        init: info.label_is_true("manager_init"),
+        version,
+    }
+}
+
+fn print_table(entries: &[ContainerEntry]) {
+    let header = ["NAME", "IMAGE", "STATE", "HOSTNAME", "HOME", "INIT", "VERSION"];
+    let rows: Vec<[String; 7]> = entries.iter()
+        .map(|x| [
+            x.name.clone(),
+            x.image.clone(),
+            x.state.clone(),
+            x.hostname.clone(),
+            x.home.clone().unwrap_or("-".into()),
+            if x.init { "yes".into() } else { "no".into() },
+            x.version.clone().unwrap_or("-".into()),
+        ])
+        .collect();
+
+    let mut widths = header.map(|x| x.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    let print_row = |row: &[&str]| {
+        let line: Vec<String> = row.iter()
+            .zip(widths.iter())
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect();
+
+        println!("{}", line.join("  ").trim_end());
+    };
+
+    print_row(&header);
+    for row in &rows {
+        print_row(&row.each_ref().map(|x| x.as_str()));
+    }
+}
+
+pub fn cmd_list(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdListArgs) -> Result<()> {
+    let state_filter = if cmd_args.running {
+        Some("running".to_string())
+    } else {
+        cmd_args.state.clone()
+    };
+
+    let mut containers: Vec<ContainerInfo> = vec![];
+    for name in util::list_owned_containers(backend)? {
+        // the container could be removed in the meantime
+        if let Some(info) = backend.inspect(&name)? {
+            containers.push(info);
+        }
+    }
+
+    for name in util::list_adopted_containers(args)? {
+        if containers.iter().any(|x| x.name == name) {
+            continue;
+        }
+
+        if let Some(info) = backend.inspect(&name)? {
+            if util::is_adopted_container(args, &info)? {
+                containers.push(info);
+            }
+        }
+    }
+
+    let mut entries: Vec<ContainerEntry> = vec![];
+    for info in &containers {
+        let entry = get_container_entry(info);
+
+        if let Some(state) = &state_filter {
+            if &entry.state != state {
+                continue;
+            }
+        }
+
+        if let Some(image) = &cmd_args.image {
+            if !entry.image.contains(image.as_str()) {
+                continue;
+            }
+        }
+
+        entries.push(entry);
+    }
+
+    if cmd_args.json {
+        println!("{}", serde_json::to_string_pretty(&entries)
+            .with_context(|| "failed to serialize container list")?);
+    } else {
+        print_table(&entries);
+    }
+
+    Ok(())
+}
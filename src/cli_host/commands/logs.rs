@@ -0,0 +1,28 @@
+//! Module contains logs command
+
+use std::io::BufRead;
+use crate::backend::ContainerBackend;
+use crate::cli_host::util;
+use crate::init_log::InitRecord;
+use crate::{Context, Result};
+use super::super::cli::{Cli, CmdLogsArgs};
+
+pub fn cmd_logs(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdLogsArgs) -> Result<()> {
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    let reader = backend.logs(&cmd_args.container_name, cmd_args.follow)?;
+
+    for line in reader.lines() {
+        let line = line.with_context(|| format!("failed to read logs of container '{}'", &cmd_args.container_name))?;
+
+        match InitRecord::from_line(&line) {
+            Some(record) => println!("{}", record.pretty()),
+            None if !cmd_args.init => println!("{}", line),
+            None => {},
+        }
+    }
+
+    Ok(())
+}
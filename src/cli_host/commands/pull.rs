@@ -0,0 +1,31 @@
+//! Module contains pull command
+
+use crate::backend::ContainerBackend;
+use crate::cli_host::util;
+use crate::{Context, Result};
+use super::super::cli::{Cli, CmdPullArgs};
+use std::path::Path;
+
+pub fn cmd_pull(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdPullArgs) -> Result<()> {
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    // copy into the directory like cp does
+    let destination = if cmd_args.destination.is_dir() {
+        let name = Path::new(&cmd_args.source).file_name()
+            .with_context(|| format!("invalid source '{}'", &cmd_args.source))?;
+
+        cmd_args.destination.join(name)
+    } else {
+        cmd_args.destination.clone()
+    };
+
+    backend.cp_from(&cmd_args.container_name, &cmd_args.source, &destination)?;
+
+    if args.verbose >= 1 {
+        println!("Copied {}:{} to {:?}", &cmd_args.container_name, &cmd_args.source, &destination);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! Module contains push command
+
+use crate::backend::ContainerBackend;
+use crate::cli_host::util;
+use crate::{Error, Result};
+use super::super::cli::{Cli, CmdPushArgs};
+
+pub fn cmd_push(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdPushArgs) -> Result<()> {
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    if !cmd_args.source.exists() {
+        return Err(Error::msg(format!("source {:?} does not exist", &cmd_args.source)));
+    }
+
+    if !cmd_args.destination.starts_with('/') {
+        return Err(Error::msg(format!("destination '{}' has to be an absolute path", &cmd_args.destination)));
+    }
+
+    backend.cp(&cmd_args.source, &cmd_args.container_name, &cmd_args.destination)?;
+
+    if args.verbose >= 1 {
+        println!("Copied {:?} to {}:{}", &cmd_args.source, &cmd_args.container_name, &cmd_args.destination);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! Module contains set-hostname command
+
+use crate::backend::ContainerBackend;
+use crate::cli_host::util;
+use crate::host_exec::CONTAINER_SOCKET_DIR;
+use crate::{Context, Error, Result};
+use super::super::cli::{Cli, CmdSetHostnameArgs};
+
+pub fn cmd_set_hostname(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdSetHostnameArgs) -> Result<()> {
+    util::validate_hostname(&cmd_args.hostname)?;
+
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    // containers created by older versions or adopted do not have the directory mounted
+    if !info.mounts.iter().any(|x| x.destination == CONTAINER_SOCKET_DIR) {
+        return Err(Error::msg(format!("container '{}' does not have {} mounted, recreate it to change its hostname", &cmd_args.container_name, CONTAINER_SOCKET_DIR)));
+    }
+
+    let dir = util::get_host_exec_dir(args, &cmd_args.container_name);
+    let path = dir.join(crate::HOSTNAME_FILE);
+    if args.dry_run {
+        println!("echo {:?} > {:?}", cmd_args.hostname, path);
+    } else {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create directory {:?}", dir))?;
+        std::fs::write(&path, format!("{}\n", cmd_args.hostname))
+            .with_context(|| format!("failed to write {:?}", path))?;
+    }
+
+    if args.verbose >= 1 && !args.dry_run {
+        if info.is_running() {
+            println!("Hostname of {} set to {}, restart the container to apply it", &cmd_args.container_name, &cmd_args.hostname);
+        } else {
+            println!("Hostname of {} set to {}", &cmd_args.container_name, &cmd_args.hostname);
+        }
+    }
+
+    Ok(())
+}
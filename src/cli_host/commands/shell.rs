@@ -1,92 +1,69 @@
 //! Module contains shell command
 
-use crate::cli_host::cli::ContainerManager;
+use std::process::exit;
+use crate::backend::{ContainerBackend, ExecOptions};
 use crate::cli_host::util;
-use crate::{Result, Context, Error};
+use crate::{Result, Context};
 use super::super::cli::{Cli, CmdShellArgs};
 
-fn generate_shell_command(args: &Cli, cmd_args: &CmdShellArgs, home: &String) -> Result<Vec<String>> {
-    // TODO move all of this into /init.sh script
-    // TODO filter the env better and allow some useful vars like DISPLAY etc
-    let mut cmd: Vec<String> = vec![
-        "exec".into(),
-        "--interactive".into(),
-        "--detach-keys=".into(),
-        "--user".into(), "root".into(),
-        format!("--workdir={}", cmd_args.workdir.as_ref().unwrap()),
-        "--env".into(), format!("CONTAINER_ID={}", cmd_args.container_name),
-        "--env".into(), "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin:/usr/games".into(),
-        // TODO ensure XDG_DATA_DIRS has /usr/local/share /usr/share
-        // TODO ensure XDG_CONFIG_DIRS has /etc/xdg
-        "--env".into(), format!("XDG_DATA_DIRS={}", std::env::var("XDG_DATA_DIRS").unwrap_or("/usr/local/share:/usr/share".into())),
-        "--env".into(), format!("XDG_CONFIG_DIRS={}", std::env::var("XDG_CONFIG_DIRS").unwrap_or("/etc/xdg".into())),
-        "--env".into(), format!("XDG_CACHE_HOME={}/.cache", &home),
-        "--env".into(), format!("XDG_CONFIG_HOME={}/.config", &home),
-        "--env".into(), format!("XDG_DATA_HOME={}/.local/share", &home),
-        "--env".into(), format!("XDG_STATE_HOME={}/.local/state", &home),
-    ];
-
-    for i in &cmd_args.env {
-        cmd.extend([
-            "--env".into(), i.into(),
-        ]);
-    }
-
-    if !cmd_args.headless {
-        cmd.push("--tty".into());
-    }
-
-    cmd.extend(cmd_args.extra_args.clone());
-
-    cmd.push(cmd_args.container_name.clone());
+fn generate_shell_options(cmd_args: &CmdShellArgs, home: &str) -> Result<ExecOptions> {
+    let mut opts = util::generate_exec_options(
+        &cmd_args.container_name,
+        cmd_args.workdir.as_ref().unwrap(),
+        home,
+        &cmd_args.env,
+        &cmd_args.extra_args,
+        !cmd_args.headless,
+    );
 
     let user_id = users::get_current_username().with_context(|| "could not get host username")?.into_string().unwrap();
 
+    opts.command = util::sudo_command(&opts, user_id);
+
     if cmd_args.login {
-        cmd.extend([
-           "sudo".into(), "-u".into(), user_id, "-i".into(),
-        ]);
+        opts.command.push("-i".into());
     } else {
-        cmd.extend([
-           "sudo".into(), "-u".into(), user_id, "-s".into(),
-        ]);
+        opts.command.push("-s".into());
     }
 
-    Ok(cmd)
+    Ok(opts)
 }
 
-pub fn cmd_shell(args: &Cli, mut cmd_args: CmdShellArgs) -> Result<()> {
-    // TODO start the container if not running already?
-
+/// Executes the shell and returns its exit code
+pub fn shell(args: &Cli, backend: &dyn ContainerBackend, mut cmd_args: CmdShellArgs) -> Result<i32> {
     // check if container already exists
-    let state = util::get_container_state(args.manager.as_ref().unwrap(), &cmd_args.container_name)?;
-    if state.is_none() {
-        return Err(Error::msg(format!("container '{}' does not exist", &cmd_args.container_name)));
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    if !info.is_running() {
+        if args.verbose >= 1 && !args.dry_run {
+            println!("Starting container {}", &cmd_args.container_name);
+        }
+
+        backend.start(&cmd_args.container_name)?;
     }
 
-    let env_vars = util::get_container_env(args.manager.as_ref().unwrap(), &cmd_args.container_name)?
-        .with_context(|| format!("could not inspect env variables of container '{}'", &cmd_args.container_name))?;
+    // wait for init even if the container was started by something else just now
+    if !args.dry_run {
+        util::wait_for_init(backend, &cmd_args.container_name, util::INIT_TIMEOUT)?;
+    }
 
-    let home = env_vars.get("HOME").with_context(|| format!("could not inspect HOME variable from container '{}'", &cmd_args.container_name))?;
+    let home = util::get_container_home(&info)?;
 
     // default workdir to home
     if cmd_args.workdir.is_none() {
-        cmd_args.workdir = Some(home.clone().into());
+        cmd_args.workdir = Some(home.clone());
     }
 
-    let cmd = generate_shell_command(args, &cmd_args, &home)?;
+    let opts = generate_shell_options(&cmd_args, &home)?;
 
-    if args.dry_run {
-        print!("{}", args.manager.unwrap().get_executable_name());
-        for arg in cmd {
-            print!(" {}", arg);
-        }
-        println!();
-        return Ok(());
-    }
+    backend.exec(&opts)
+}
 
-    // TODO run command
+pub fn cmd_shell(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdShellArgs) -> Result<()> {
+    let rc = shell(args, backend, cmd_args)?;
 
-    Ok(())
+    // exit with same exit code
+    exit(rc);
 }
-
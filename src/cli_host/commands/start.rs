@@ -0,0 +1,37 @@
+//! Module contains start command
+
+use std::time::Duration;
+use crate::backend::ContainerBackend;
+use crate::cli_host::util;
+use crate::Result;
+use super::super::cli::{Cli, CmdStartArgs};
+
+pub fn cmd_start(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdStartArgs) -> Result<()> {
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    if args.dry_run {
+        return backend.start(&cmd_args.container_name);
+    }
+
+    if info.is_running() {
+        if args.verbose >= 1 {
+            println!("Container {} is already running", &cmd_args.container_name);
+        }
+    } else {
+        if args.verbose >= 1 {
+            println!("Starting container {}", &cmd_args.container_name);
+        }
+
+        backend.start(&cmd_args.container_name)?;
+    }
+
+    util::wait_for_init(backend, &cmd_args.container_name, Duration::from_secs(cmd_args.timeout))?;
+
+    if args.verbose >= 1 {
+        println!("Container successfully started");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Module contains stop command
+
+use std::time::Duration;
+use crate::backend::{ContainerBackend, ContainerInfo};
+use crate::cli_host::util;
+use crate::{Result, Error};
+use super::super::cli::{Cli, CmdStopArgs};
+use super::create::INIT_STOP_SIGNAL;
+
+/// Returns signal that stops the container gracefully
+fn get_stop_signal(info: &ContainerInfo) -> &'static str {
+    // containers with init system need special signal to shutdown properly
+    if info.label_is_true("manager_init") {
+        INIT_STOP_SIGNAL
+    } else {
+        "SIGTERM"
+    }
+}
+
+/// Stops the container gracefully and kills it on timeout if force is set
+pub fn stop_container(args: &Cli, backend: &dyn ContainerBackend, info: &ContainerInfo, force: bool, timeout: Duration) -> Result<()> {
+    let container_name = info.name.as_str();
+    let signal = get_stop_signal(info);
+
+    if args.dry_run {
+        backend.stop(container_name, signal)?;
+        // the kill only happens on timeout
+        if force {
+            println!("# if it does not stop in {} seconds", timeout.as_secs());
+            backend.kill(container_name)?;
+        }
+        return Ok(());
+    }
+
+    if args.verbose >= 1 {
+        println!("Stopping container {}", container_name);
+    }
+
+    backend.stop(container_name, signal)?;
+
+    if backend.wait_for_stop(container_name, timeout)? {
+        return Ok(());
+    }
+
+    if !force {
+        return Err(Error::msg(format!("container '{}' did not stop in {} seconds, use --force to kill it", container_name, timeout.as_secs())));
+    }
+
+    if args.verbose >= 1 {
+        println!("Container {} did not stop in time, killing it", container_name);
+    }
+
+    backend.kill(container_name)?;
+
+    if !backend.wait_for_stop(container_name, timeout)? {
+        return Err(Error::msg(format!("container '{}' could not be killed", container_name)));
+    }
+
+    Ok(())
+}
+
+pub fn cmd_stop(args: &Cli, backend: &dyn ContainerBackend, cmd_args: CmdStopArgs) -> Result<()> {
+    let info = util::get_container(backend, &cmd_args.container_name)?;
+
+    util::ensure_owned_container(args, &info)?;
+
+    if !info.is_running() {
+        if args.verbose >= 1 {
+            println!("Container {} is not running", &cmd_args.container_name);
+        }
+
+        return Ok(());
+    }
+
+    stop_container(args, backend, &info, cmd_args.force, Duration::from_secs(cmd_args.timeout))?;
+
+    if args.verbose >= 1 && !args.dry_run {
+        println!("Container successfully stopped");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,338 @@
+//! Host commands driven through the recording mock, the commands executed are checked instead of
+//! their effect on a real container manager
+
+use super::super::cli::{Cli, CliCommands};
+use super::exec::generate_exec_options;
+use super::shell::shell;
+use super::{cmd_create, cmd_destroy, cmd_set_hostname, cmd_stop};
+use crate::backend::mock::{MockScript, RecordingRunner};
+use crate::backend::Podman;
+use crate::{Result, CONTAINER_SCHEMA, INIT_MARKER};
+use clap::Parser;
+use serde_json::json;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Fake container manager with its own directory for files written on host, the directory is
+/// deleted on drop
+struct Setup {
+    dir: PathBuf,
+    runner: Rc<RecordingRunner>,
+}
+
+impl Setup {
+    fn new(name: &str, containers: &[(&str, serde_json::Value)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("lm-host-command-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let script = MockScript {
+            containers: containers.iter().map(|(name, inspect)| (name.to_string(), inspect.clone())).collect(),
+            ..Default::default()
+        };
+
+        Self { dir, runner: Rc::new(RecordingRunner::new(script)) }
+    }
+
+    fn data_dir(&self) -> PathBuf {
+        self.dir.join("data")
+    }
+
+    fn parse(&self, args: &[&str]) -> Cli {
+        let data_dir = self.data_dir();
+        let global = ["lm", "--manager", "podman", "--data-dir", data_dir.to_str().unwrap()];
+
+        Cli::try_parse_from([&global, args].concat()).unwrap()
+    }
+
+    /// Runs the command like main does, returns exit code of commands that have one
+    fn run(&self, args: &[&str]) -> Result<i32> {
+        let args = self.parse(args);
+        let backend = Podman::new(args.dry_run, self.runner.clone());
+
+        match &args.cmd {
+            CliCommands::Create(cmd_args) => cmd_create(&args, &backend, cmd_args.clone()).map(|_| 0),
+            CliCommands::Shell(cmd_args) => shell(&args, &backend, cmd_args.clone()),
+            CliCommands::Stop(cmd_args) => cmd_stop(&args, &backend, cmd_args.clone()).map(|_| 0),
+            CliCommands::Destroy(cmd_args) => cmd_destroy(&args, &backend, cmd_args.clone()).map(|_| 0),
+            CliCommands::SetHostname(cmd_args) => cmd_set_hostname(&args, &backend, cmd_args.clone()).map(|_| 0),
+            x => unimplemented!("{:?}", x),
+        }
+    }
+
+    /// Returns recorded commands starting with the arguments, the executable is skipped
+    fn find_commands(&self, prefix: &[&str]) -> Vec<Vec<String>> {
+        self.runner.commands().into_iter()
+            .filter(|x| x.len() > prefix.len() && x[1..=prefix.len()].iter().zip(prefix).all(|(a, b)| a == b))
+            .collect()
+    }
+}
+
+impl Drop for Setup {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Inspect output of a container made by legumemanager
+fn owned_container(name: &str, state: &str) -> serde_json::Value {
+    json!({
+        "Id": format!("mock-{}", name),
+        "Name": name,
+        "State": { "Status": state },
+        "Config": {
+            "Env": ["HOME=/home/test"],
+            "Labels": {
+                "manager": "legumemanager",
+                "manager_schema": CONTAINER_SCHEMA.to_string(),
+                "manager_init": "false",
+            },
+        },
+    })
+}
+
+/// Returns values of the flag in the command
+fn flag_values<'a>(command: &'a [String], flag: &str) -> Vec<&'a str> {
+    command.windows(2)
+        .filter(|x| x[0] == flag)
+        .map(|x| x[1].as_str())
+        .collect()
+}
+
+#[test]
+fn create() {
+    let setup = Setup::new("create", &[]);
+    let home = setup.dir.join("home");
+
+    setup.run(&["create", "--home", home.to_str().unwrap(), "--hostname", "pet", "-e", "A=b", "--host-command", "flatpak", "lm-create", "alpine"]).unwrap();
+
+    let commands = setup.runner.commands();
+    assert_eq!(commands[0], ["podman", "container", "inspect", "lm-create"]);
+
+    let create = &setup.find_commands(&["create"])[0];
+    assert_eq!(flag_values(create, "--name"), ["lm-create"]);
+    assert_eq!(flag_values(create, "--hostname"), ["pet"]);
+    assert_eq!(flag_values(create, "--userns"), ["keep-id"]);
+    assert_eq!(flag_values(create, "--entrypoint"), [r#"["/lm","init"]"#]);
+    assert!(flag_values(create, "--label").contains(&"manager=legumemanager"));
+    assert!(flag_values(create, "--env").contains(&"A=b"));
+    assert!(flag_values(create, "--env").contains(&format!("HOME={}", home.display()).as_str()));
+    assert_eq!(create.last().unwrap(), "alpine");
+
+    // sources of the mount spec are inside it
+    assert!(flag_values(create, "--volume").contains(&"/:/run/host:rslave"));
+
+    // mountpoints inside it would be created in root of the host
+    assert!(flag_values(create, "--volume").iter().all(|x| !x.split(':').nth(1).unwrap().starts_with("/run/host/")));
+
+    let cp = setup.find_commands(&["container", "cp"]);
+    assert_eq!(cp[0].last().unwrap(), "lm-create:/lm");
+
+    // mount spec is not pushed when it is the default
+    assert_eq!(cp.len(), 2);
+    assert_eq!(cp[1].last().unwrap(), "lm-create:/etc/legumemanager-command-fallback.json");
+
+    assert!(home.is_dir());
+    assert!(setup.data_dir().join("host-exec/lm-create").is_dir());
+}
+
+#[test]
+fn create_dry_run() {
+    let setup = Setup::new("create-dry-run", &[]);
+    let home = setup.dir.join("home");
+    std::fs::create_dir_all(&home).unwrap();
+
+    setup.run(&["--dry-run", "create", "--home", home.to_str().unwrap(), "--hostname", "pet", "lm-dry-run", "alpine"]).unwrap();
+
+    // commands that change anything are printed instead
+    assert_eq!(setup.runner.commands(), [["podman", "container", "inspect", "lm-dry-run"]]);
+    assert!(!setup.data_dir().join("host-exec/lm-dry-run").exists());
+}
+
+#[test]
+fn create_already_exists() {
+    let setup = Setup::new("create-already-exists", &[("lm-exists", owned_container("lm-exists", "exited"))]);
+
+    let err = setup.run(&["create", "lm-exists", "alpine"]).unwrap_err();
+
+    assert_eq!(err.to_string(), "container 'lm-exists' already exists");
+    assert_eq!(setup.runner.commands(), [["podman", "container", "inspect", "lm-exists"]]);
+}
+
+#[test]
+fn create_session_bus_needs_policy_all() {
+    let setup = Setup::new("create-session-bus", &[]);
+
+    let err = setup.run(&["create", "--session-bus", "--host-exec-policy", "allowlist", "lm-bus", "alpine"]).unwrap_err();
+
+    assert!(format!("{:#}", err).contains("--session-bus"), "{:#}", err);
+    assert!(setup.find_commands(&["create"]).is_empty());
+}
+
+#[test]
+fn shell_starts_container() {
+    let setup = Setup::new("shell", &[("lm-shell", owned_container("lm-shell", "exited"))]);
+
+    assert_eq!(setup.run(&["shell", "--login", "lm-shell"]).unwrap(), 0);
+
+    assert_eq!(setup.find_commands(&["start"]), [["podman", "start", "lm-shell"]]);
+
+    // init is checked before the shell is executed
+    let execs = setup.find_commands(&["exec"]);
+    assert_eq!(execs[0][execs[0].len() - 4..], ["lm-shell", "test", "-e", INIT_MARKER]);
+
+    let shell = execs.last().unwrap();
+    let user = users::get_current_username().unwrap().into_string().unwrap();
+    assert_eq!(shell[shell.len() - 6], "lm-shell");
+    assert_eq!(shell[shell.len() - 5], "sudo");
+    assert_eq!(shell[shell.len() - 3..], ["-u", user.as_str(), "-i"]);
+    assert!(shell.contains(&"--workdir=/home/test".to_string()));
+    assert!(shell.contains(&"--tty".to_string()));
+    assert_eq!(flag_values(shell, "--user"), ["root"]);
+}
+
+/// Returns variables kept by sudo in the command
+fn preserved_env(command: &[String]) -> Vec<&str> {
+    command.iter()
+        .find_map(|x| x.strip_prefix("--preserve-env="))
+        .unwrap()
+        .split(',')
+        .collect()
+}
+
+#[test]
+fn shell_preserves_env() {
+    let setup = Setup::new("shell-env", &[("lm-env", owned_container("lm-env", "running"))]);
+
+    setup.run(&["shell", "-e", "A=b", "-e", "PATH=/bin", "lm-env"]).unwrap();
+
+    let shell = setup.find_commands(&["exec"]).pop().unwrap();
+    assert!(flag_values(&shell, "--env").contains(&"A=b"));
+
+    // sudo resets the environment passed to the manager
+    let preserved = preserved_env(&shell);
+    assert!(preserved.contains(&"A"));
+    assert!(preserved.contains(&"XDG_CONFIG_HOME"));
+    assert_eq!(preserved.iter().filter(|x| **x == "PATH").count(), 1);
+}
+
+#[test]
+fn exec_preserves_env() {
+    let args = Setup::new("exec-env", &[]).parse(&["exec", "-w", "/tmp", "-e", "A=b", "lm-exec", "env"]);
+    let CliCommands::Exec(cmd_args) = args.cmd else { unreachable!() };
+
+    let opts = generate_exec_options(&cmd_args, "/home/test").unwrap();
+
+    assert_eq!(opts.command[0], "sudo");
+    assert!(preserved_env(&opts.command).contains(&"A"));
+    assert!(preserved_env(&opts.command).contains(&"XDG_DATA_HOME"));
+    assert_eq!(opts.command[opts.command.len() - 2..], ["--", "env"]);
+}
+
+#[test]
+fn shell_missing_container() {
+    let setup = Setup::new("shell-missing", &[]);
+
+    let err = setup.run(&["shell", "lm-missing"]).unwrap_err();
+
+    assert_eq!(err.to_string(), "container 'lm-missing' does not exist");
+    assert_eq!(setup.runner.commands(), [["podman", "container", "inspect", "lm-missing"]]);
+}
+
+#[test]
+fn shell_foreign_container() {
+    let mut inspect = owned_container("lm-foreign", "running");
+    inspect["Config"]["Labels"] = json!({});
+    let setup = Setup::new("shell-foreign", &[("lm-foreign", inspect)]);
+
+    assert!(setup.run(&["shell", "lm-foreign"]).is_err());
+    assert!(setup.find_commands(&["exec"]).is_empty());
+}
+
+#[test]
+fn stop_dry_run() {
+    let setup = Setup::new("stop-dry-run", &[("lm-stop", owned_container("lm-stop", "running"))]);
+
+    setup.run(&["--dry-run", "stop", "--force", "lm-stop"]).unwrap();
+
+    // nothing is waited for or killed
+    assert_eq!(setup.runner.commands(), [["podman", "container", "inspect", "lm-stop"]]);
+}
+
+#[test]
+fn destroy_running_container() {
+    let setup = Setup::new("destroy", &[("lm-destroy", owned_container("lm-destroy", "running"))]);
+
+    setup.run(&["destroy", "--force", "lm-destroy"]).unwrap();
+
+    let changes: Vec<Vec<String>> = setup.runner.commands().into_iter()
+        .filter(|x| x[1] != "container")
+        .collect();
+
+    assert_eq!(changes, [
+        vec!["podman", "kill", "--signal", "SIGTERM", "lm-destroy"],
+        vec!["podman", "rm", "lm-destroy"],
+    ]);
+}
+
+#[test]
+fn destroy_missing_container() {
+    let setup = Setup::new("destroy-missing", &[]);
+
+    let err = setup.run(&["destroy", "--force", "lm-gone"]).unwrap_err();
+
+    assert_eq!(err.to_string(), "container 'lm-gone' does not exist");
+    assert!(setup.find_commands(&["rm"]).is_empty());
+}
+
+#[test]
+fn set_hostname() {
+    let mut inspect = owned_container("lm-hostname", "running");
+    inspect["Mounts"] = json!([{ "Type": "bind", "Source": "/somewhere", "Destination": "/run/legumemanager", "RW": true }]);
+    let setup = Setup::new("set-hostname", &[("lm-hostname", inspect)]);
+
+    setup.run(&["set-hostname", "lm-hostname", "pet"]).unwrap();
+
+    // nothing is changed inside the container
+    assert_eq!(setup.runner.commands(), [["podman", "container", "inspect", "lm-hostname"]]);
+    assert_eq!(std::fs::read_to_string(setup.data_dir().join("host-exec/lm-hostname/hostname")).unwrap(), "pet\n");
+
+    assert!(setup.run(&["set-hostname", "lm-hostname", "pet_1"]).is_err());
+}
+
+#[test]
+fn set_hostname_without_directory() {
+    let setup = Setup::new("set-hostname-old", &[("lm-old", owned_container("lm-old", "exited"))]);
+
+    let err = setup.run(&["set-hostname", "lm-old", "pet"]).unwrap_err();
+
+    assert!(err.to_string().contains("recreate it"), "{}", err);
+    assert!(!setup.data_dir().join("host-exec/lm-old").exists());
+}
+
+#[test]
+fn container_without_schema_is_owned() {
+    let mut inspect = owned_container("lm-schema", "running");
+    inspect["Config"]["Labels"].as_object_mut().unwrap().remove("manager_schema");
+    let setup = Setup::new("schema", &[("lm-schema", inspect)]);
+
+    assert_eq!(setup.run(&["shell", "lm-schema"]).unwrap(), 0);
+    assert!(!setup.find_commands(&["exec"]).is_empty());
+}
+
+#[test]
+fn adopt_foreign_container() {
+    let mut inspect = owned_container("lm-adopt", "running");
+    inspect["Config"]["Labels"] = json!({});
+    let setup = Setup::new("adopt", &[("lm-adopt", inspect)]);
+    let adopted = setup.data_dir().join("adopted/lm-adopt");
+
+    setup.run(&["--dry-run", "--adopt", "shell", "lm-adopt"]).unwrap();
+    assert!(!adopted.exists());
+
+    setup.run(&["--adopt", "shell", "lm-adopt"]).unwrap();
+    assert_eq!(std::fs::read_to_string(&adopted).unwrap(), "mock-lm-adopt");
+
+    // remembered without --adopt
+    setup.run(&["shell", "lm-adopt"]).unwrap();
+}
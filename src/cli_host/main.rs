@@ -1,6 +1,8 @@
 use clap::Parser;
 use super::cli::{Cli, CliCommands, ContainerManager};
 use super::commands;
+use super::util;
+use crate::backend;
 use crate::Result;
 
 pub fn main() -> Result<()> {
@@ -11,14 +13,32 @@ pub fn main() -> Result<()> {
         args.manager = Some(ContainerManager::find_available().expect("no container manager found!"));
     }
 
+    if args.data_dir.is_none() {
+        args.data_dir = Some(util::get_default_data_dir()?);
+    }
+
     // if quiet stay quiet
     if args.quiet {
         args.verbose = 0;
     }
 
+    let backend = backend::get_backend(args.manager.unwrap(), args.dry_run)?;
+    let backend = backend.as_ref();
+
     match &args.cmd {
-        CliCommands::Create(cmd_args) => commands::cmd_create(&args, cmd_args.clone()),
-        CliCommands::Shell(cmd_args) => commands::cmd_shell(&args, cmd_args.clone()),
+        CliCommands::Create(cmd_args) => commands::cmd_create(&args, backend, cmd_args.clone()),
+        CliCommands::Shell(cmd_args) => commands::cmd_shell(&args, backend, cmd_args.clone()),
+        CliCommands::Exec(cmd_args) => commands::cmd_exec(&args, backend, cmd_args.clone()),
+        CliCommands::List(cmd_args) => commands::cmd_list(&args, backend, cmd_args.clone()),
+        CliCommands::Start(cmd_args) => commands::cmd_start(&args, backend, cmd_args.clone()),
+        CliCommands::Stop(cmd_args) => commands::cmd_stop(&args, backend, cmd_args.clone()),
+        CliCommands::Destroy(cmd_args) => commands::cmd_destroy(&args, backend, cmd_args.clone()),
+        CliCommands::Push(cmd_args) => commands::cmd_push(&args, backend, cmd_args.clone()),
+        CliCommands::Pull(cmd_args) => commands::cmd_pull(&args, backend, cmd_args.clone()),
+        CliCommands::SetHostname(cmd_args) => commands::cmd_set_hostname(&args, backend, cmd_args.clone()),
+        CliCommands::DebugInit(cmd_args) => commands::cmd_debug_init(&args, backend, cmd_args.clone()),
+        CliCommands::Logs(cmd_args) => commands::cmd_logs(&args, backend, cmd_args.clone()),
+        CliCommands::HostDaemon(cmd_args) => commands::cmd_host_daemon(&args, backend, cmd_args.clone()),
         _ => Ok(()),
     }
 
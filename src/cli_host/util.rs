@@ -1,84 +1,322 @@
-use std::{path::PathBuf, process::Command};
-use super::cli::ContainerManager;
+use std::fs::{DirBuilder, OpenOptions};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::io::Write;
+use crate::backend::{ContainerBackend, ContainerInfo, ExecOptions};
+use super::cli::Cli;
+use serde::Serialize;
 use crate::{Context, Error, Result};
-use std::collections::HashMap;
 
-/// Inspects container and returns json, if container does not exist it will return None
-pub fn container_inspect(manager: &ContainerManager, container_name: &str, format: &str) -> Result<Option<String>> {
-    let manager_exe = manager.get_executable_name();
-    let output = Command::new(manager_exe)
-        .args(["container", "inspect", "--format", format, container_name])
-        .output()
-        .with_context(|| format!("unable to execute manager '{}'", manager_exe))?;
+/// Returns names of all containers made by legumemanager
+pub fn list_owned_containers(backend: &dyn ContainerBackend) -> Result<Vec<String>> {
+    backend.list("manager=legumemanager")
+}
 
-    let output_stdout = String::from_utf8(output.stdout).unwrap();
+/// Returns information about the container, fails if it does not exist
+pub fn get_container(backend: &dyn ContainerBackend, container_name: &str) -> Result<ContainerInfo> {
+    backend.inspect(container_name)?
+        .with_context(|| format!("container '{}' does not exist", container_name))
+}
 
-    // if it has failed then container probably does not exist
-    if !output.status.success() {
-        return Ok(None);
-    }
+/// Default time to wait for container init to finish
+pub const INIT_TIMEOUT: Duration = Duration::from_secs(30);
 
-    Ok(Some(output_stdout))
+/// Checks if the file exists inside the container
+fn container_file_exists(backend: &dyn ContainerBackend, container_name: &str, path: &str) -> Result<bool> {
+    let rc = backend.exec(&ExecOptions {
+        container_name: container_name.into(),
+        quiet: true,
+        command: vec!["test".into(), "-e".into(), path.into()],
+        ..Default::default()
+    })?;
+
+    Ok(rc == 0)
 }
 
-/// Returns container state from manager
-pub fn get_container_state(manager: &ContainerManager, container_name: &str) -> Result<Option<String>> {
-    container_inspect(manager, container_name, "{{.State.Status}}")
+/// Checks if init inside the container has finished
+pub fn is_container_initialized(backend: &dyn ContainerBackend, container_name: &str) -> Result<bool> {
+    container_file_exists(backend, container_name, crate::INIT_MARKER)
 }
 
-/// Returns container env variables from manager
-pub fn get_container_env(manager: &ContainerManager, container_name: &str) -> Result<Option<HashMap<String, String>>> {
-    // TODO clean this up bit later, this is messy
-    // NOTE: this formats the json array in key=val format
-    if let Some(env_str) = container_inspect(manager, container_name, r#"{{ range .Config.Env }}{{ . }}{{ printf "\n" }}{{ end }}"#)? {
-        let env_str_lines: Vec<&str> = env_str.split('\n').collect();
-        let mut map: HashMap<String, String> = HashMap::new();
+/// Waits until init inside the container has finished or until timeout is reached, time spent
+/// installing essential packages on first start does not count
+pub fn wait_for_init(backend: &dyn ContainerBackend, container_name: &str, timeout: Duration) -> Result<()> {
+    let mut start = Instant::now();
+    let mut bootstrapping = false;
+
+    loop {
+        // stop waiting if the container stopped, init has probably failed
+        let info = get_container(backend, container_name)?;
+        if !info.is_running() {
+            return Err(Error::msg(format!("container '{}' stopped during init (state '{}'), check the container logs", container_name, info.state.status)));
+        }
+
+        if is_container_initialized(backend, container_name)? {
+            return Ok(());
+        }
+
+        if start.elapsed() >= timeout {
+            if !container_file_exists(backend, container_name, crate::BOOTSTRAP_MARKER)? {
+                return Err(Error::msg(format!("timed out waiting for container '{}' to initialize", container_name)));
+            }
 
-        for line in env_str_lines {
-            if let Some((before, after)) = line.split_once('=') {
-                map.insert(before.into(), after.into());
+            if !bootstrapping {
+                eprintln!("Waiting for essential packages to be installed in container '{}'", container_name);
+                bootstrapping = true;
             }
+
+            start = Instant::now();
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Returns HOME variable of the container
+pub fn get_container_home(info: &ContainerInfo) -> Result<String> {
+    info.env_var("HOME")
+        .map(|x| x.to_string())
+        .with_context(|| format!("could not inspect HOME variable from container '{}'", info.name))
+}
+
+/// Generates exec options shared by shell and exec commands, the command to execute should be
+/// set afterwards
+pub fn generate_exec_options(container_name: &str, workdir: &str, home: &str, env: &[String], extra_args: &[String], tty: bool) -> ExecOptions {
+    // TODO move all of this into /init.sh script
+    // TODO filter the env better and allow some useful vars like DISPLAY etc
+    let mut exec_env: Vec<String> = vec![
+        format!("CONTAINER_ID={}", container_name),
+        "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin:/usr/games".into(),
+        // TODO ensure XDG_DATA_DIRS has /usr/local/share /usr/share
+        // TODO ensure XDG_CONFIG_DIRS has /etc/xdg
+        format!("XDG_DATA_DIRS={}", std::env::var("XDG_DATA_DIRS").unwrap_or("/usr/local/share:/usr/share".into())),
+        format!("XDG_CONFIG_DIRS={}", std::env::var("XDG_CONFIG_DIRS").unwrap_or("/etc/xdg".into())),
+        format!("XDG_CACHE_HOME={}/.cache", home),
+        format!("XDG_CONFIG_HOME={}/.config", home),
+        format!("XDG_DATA_HOME={}/.local/share", home),
+        format!("XDG_STATE_HOME={}/.local/state", home),
+    ];
+
+    exec_env.extend(env.iter().cloned());
+
+    ExecOptions {
+        container_name: container_name.into(),
+        user: Some("root".into()),
+        workdir: Some(workdir.into()),
+        env: exec_env,
+        interactive: true,
+        tty,
+        extra_args: extra_args.to_vec(),
+        ..Default::default()
+    }
+}
+
+/// Returns sudo command running as the user that keeps the environment of exec options, sudo
+/// resets the environment otherwise
+pub fn sudo_command(opts: &ExecOptions, user: String) -> Vec<String> {
+    let mut names: Vec<&str> = vec![];
+    for name in opts.env.iter().map(|x| x.split('=').next().unwrap_or_default()) {
+        if !names.contains(&name) {
+            names.push(name);
         }
+    }
+
+    vec![
+        "sudo".into(), format!("--preserve-env={}", names.join(",")), "-u".into(), user,
+    ]
+}
+
+/// Asks user for confirmation, anything other than yes is treated as no
+pub fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)
+        .with_context(|| "failed to read answer from stdin")?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
 
-        Ok(Some(map))
-    } else {
-        Ok(None)
+/// Checks the hostname is a valid name that the kernel accepts
+pub fn validate_hostname(hostname: &str) -> Result<()> {
+    // HOST_NAME_MAX on linux
+    if hostname.is_empty() || hostname.len() > 64 {
+        return Err(Error::msg(format!("hostname '{}' has to be between 1 and 64 characters", hostname)));
     }
+
+    let valid_label = |x: &str| {
+        !x.is_empty()
+            && !x.starts_with('-')
+            && !x.ends_with('-')
+            && x.chars().all(|x| x.is_ascii_alphanumeric() || x == '-')
+    };
+
+    if !hostname.split('.').all(valid_label) {
+        return Err(Error::msg(format!("hostname '{}' may only contain letters, digits, hyphens and dots", hostname)));
+    }
+
+    Ok(())
+}
+
+/// Creates temporary directory only accessible by the current user, fails if it already exists
+/// so nothing planted by other users is reused
+fn create_private_temp_dir() -> Result<PathBuf> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+    let path = std::env::temp_dir().join(format!("lm-{}-{:x}", std::process::id(), nanos));
+
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&path)
+        .with_context(|| format!("failed to create temporary directory {:?}", path))?;
+
+    Ok(path)
+}
+
+/// Writes data into the file inside the container, the container manager can only copy files so
+/// it is written into a private temporary directory first
+pub fn push_file(backend: &dyn ContainerBackend, container_name: &str, destination: &str, data: &[u8]) -> Result<()> {
+    let dir = create_private_temp_dir()?;
+    let path = dir.join(Path::new(destination).file_name().unwrap_or("file".as_ref()));
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o644)
+        .open(&path)
+        .and_then(|mut x| x.write_all(data))
+        .with_context(|| format!("failed to write {:?}", path))
+        .and_then(|_| backend.cp(&path, container_name, destination));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    result
+}
+
+/// Writes the value as JSON into the file inside the container
+pub fn push_json_file(backend: &dyn ContainerBackend, container_name: &str, destination: &str, value: &impl Serialize) -> Result<()> {
+    let data = serde_json::to_vec_pretty(value)
+        .with_context(|| format!("failed to serialize {}", destination))?;
+
+    push_file(backend, container_name, destination, &data)
 }
 
 /// Pushes the binary into container
-pub fn push_executable_into_container(manager: &ContainerManager, container_name: &str, path: PathBuf) -> Result<()> {
-    let manager_exe = manager.get_executable_name();
-
-    // i am only testing if the container exists
-    match get_container_state(manager, container_name) {
-        // the container exists
-        Ok(Some(_)) => {
-            match std::env::current_exe() {
-                Ok(current_exe) => {
-                    let status = Command::new(manager_exe)
-                        .args(["container", "cp", current_exe.to_str().expect("error converting current_exe to &str"), format!("{}:/lm", container_name).as_str()])
-                        .status()
-                        .with_context(|| format!("unable to execute manager '{}'", manager_exe))?;
-
-                    if status.success() {
-                        Ok(())
-                    } else {
-                        Err(Error::msg(format!("Failed to copy executable into container '{}'", container_name)))
-                    }
-                }
-                Err(err) => Err(err.into()),
-            }
-        },
+pub fn push_executable_into_container(backend: &dyn ContainerBackend, container_name: &str, path: PathBuf) -> Result<()> {
+    let current_exe = std::env::current_exe()
+        .with_context(|| "failed to get path of current executable")?;
+
+    backend.cp(&current_exe, container_name, path.to_str().expect("error converting path to &str"))
+        .with_context(|| format!("Failed to copy executable into container '{}'", container_name))
+}
+
+/// Returns default directory where legumemanager keeps its data on host
+pub fn get_default_data_dir() -> Result<PathBuf> {
+    Ok(dirs::data_dir()
+        .context("failed to get data directory")?
+        .join("legumemanager"))
+}
+
+/// Returns directory where legumemanager keeps its data on host, it is set in main
+pub fn get_data_dir(args: &Cli) -> &Path {
+    args.data_dir.as_deref().expect("data directory is not set")
+}
+
+/// Returns directory containing host-exec socket directories of all containers
+pub fn get_host_exec_root(args: &Cli) -> PathBuf {
+    get_data_dir(args).join("host-exec")
+}
+
+/// Returns directory of the host-exec socket of the container, it is mounted into the container
+pub fn get_host_exec_dir(args: &Cli, container_name: &str) -> PathBuf {
+    get_host_exec_root(args).join(container_name)
+}
+
+/// Returns path of the file that marks container as adopted
+fn get_adopted_path(args: &Cli, container_name: &str) -> PathBuf {
+    get_data_dir(args).join("adopted").join(container_name)
+}
+
+/// Checks if container was adopted, the id is checked so a new container with the same name is
+/// not adopted by accident
+pub fn is_adopted_container(args: &Cli, info: &ContainerInfo) -> Result<bool> {
+    let Ok(adopted_id) = std::fs::read_to_string(get_adopted_path(args, &info.name)) else {
+        return Ok(false);
+    };
 
-        // the container does not exist
-        Ok(None) => Err(Error::msg(format!("Container '{}' does not exist", container_name))),
+    Ok(info.id == adopted_id.trim())
+}
+
+/// Remembers container as adopted
+pub fn adopt_container(args: &Cli, info: &ContainerInfo) -> Result<()> {
+    let path = get_adopted_path(args, &info.name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {:?}", parent))?;
+    }
+
+    std::fs::write(&path, &info.id)
+        .with_context(|| format!("failed to write {:?}", path))
+}
+
+/// Forgets that container was adopted, does nothing if it was not
+pub fn forget_adopted_container(args: &Cli, container_name: &str) -> Result<()> {
+    let path = get_adopted_path(args, container_name);
+    if path.exists() {
+        std::fs::remove_file(&path)
+            .with_context(|| format!("failed to delete {:?}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Returns names of all adopted containers, including ones that do not exist anymore
+pub fn list_adopted_containers(args: &Cli) -> Result<Vec<String>> {
+    let Ok(entries) = std::fs::read_dir(get_data_dir(args).join("adopted")) else {
+        return Ok(vec![]);
+    };
 
-        // Some kind of error happen
-        Err(err) => Err(err),
+    Ok(entries
+        .filter_map(|x| x.ok())
+        .filter_map(|x| x.file_name().into_string().ok())
+        .collect())
+}
+
+/// Checks if the container was created by legumemanager with a compatible schema
+pub fn is_owned_container(info: &ContainerInfo) -> Result<bool> {
+    if info.label("manager") != Some("legumemanager") {
+        return Ok(false);
+    }
+
+    // containers made before schema was introduced have the first one
+    let schema = info.label("manager_schema").unwrap_or("0");
+
+    match schema.parse::<u32>() {
+        Ok(x) if x > crate::CONTAINER_SCHEMA => Err(Error::msg(format!(
+            "container '{}' was created by a newer version of legumemanager (schema {}), please update legumemanager",
+            info.name, x
+        ))),
+        Ok(_) => Ok(true),
+        Err(_) => Ok(false),
     }
 }
 
-// TODO create is_owned_container() to check if the container is made by legumemanager, forbid
-// using it for foreign containers to avoid problems
+/// Fails if the container is not owned by legumemanager, unless it was adopted or --adopt is set
+/// in which case it will be remembered as adopted
+pub fn ensure_owned_container(args: &Cli, info: &ContainerInfo) -> Result<()> {
+    if is_owned_container(info)? || is_adopted_container(args, info)? {
+        return Ok(());
+    }
+
+    // nothing is remembered on dry run
+    if args.adopt {
+        return if args.dry_run { Ok(()) } else { adopt_container(args, info) };
+    }
+
+    Err(Error::msg(format!(
+        "container '{}' was not created by legumemanager, use --adopt to use it anyway",
+        info.name
+    )))
+}
 
@@ -0,0 +1,78 @@
+//! Which commands missing inside the container are executed on the host instead, shell hooks
+//! installed by init ask the container CLI that reads this file
+
+use crate::{util, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// File inside the container with the fallback rules, without it nothing is forwarded
+pub const COMMAND_FALLBACK_FILE: &str = "/etc/legumemanager-command-fallback.json";
+
+/// Rules for forwarding missing commands to the host
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CommandFallback {
+    /// Forward every command that is not denied, otherwise only allowed ones are forwarded
+    #[serde(default)]
+    pub allow_all: bool,
+
+    /// Commands that are forwarded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+
+    /// Commands that are never forwarded, takes priority over everything else
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+impl CommandFallback {
+    /// Loads rules from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        util::load_json(path, "command fallback rules")
+    }
+
+    /// Returns true if nothing is forwarded
+    pub fn is_disabled(&self) -> bool {
+        !self.allow_all && self.allow.is_empty()
+    }
+
+    /// Checks if the command should be executed on the host
+    pub fn is_allowed(&self, command: &str) -> bool {
+        if self.deny.iter().any(|x| x == command) {
+            return false;
+        }
+
+        self.allow_all || self.allow.iter().any(|x| x == command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deny_takes_priority() {
+        let rules = CommandFallback {
+            allow_all: true,
+            allow: vec!["rm".into()],
+            deny: vec!["rm".into()],
+        };
+
+        assert!(!rules.is_allowed("rm"));
+        assert!(rules.is_allowed("flatpak"));
+    }
+
+    #[test]
+    fn only_allowed_commands_are_forwarded() {
+        let rules = CommandFallback {
+            allow: vec!["flatpak".into()],
+            ..Default::default()
+        };
+
+        assert!(rules.is_allowed("flatpak"));
+        assert!(!rules.is_allowed("podman"));
+        assert!(!rules.is_disabled());
+
+        assert!(!CommandFallback::default().is_allowed("flatpak"));
+        assert!(CommandFallback::default().is_disabled());
+    }
+}
@@ -2,11 +2,20 @@
 
 /// Development only flag to force host mode in a container
 #[cfg(debug_assertions)]
-pub const LM_FORCE_HOST: &'static str = "LM_FORCE_HOST";
+pub const LM_FORCE_HOST: &str = "LM_FORCE_HOST";
+
+/// Development only path to JSON script of a fake container manager, every command is printed
+/// instead of executed
+#[cfg(debug_assertions)]
+pub const LM_MOCK_BACKEND: &str = "LM_MOCK_BACKEND";
+
+/// Transport used by host-exec, either 'daemon' or 'flatpak' (defaults to the host daemon if it is
+/// running, otherwise the Flatpak Development interface on the session bus)
+pub const LM_HOST_EXEC_TRANSPORT: &str = "LM_HOST_EXEC_TRANSPORT";
 
 /// Set custom home prefix
-pub const LM_HOME_PREFIX: &'static str = "LM_HOME_PREFIX";
+pub const LM_HOME_PREFIX: &str = "LM_HOME_PREFIX";
 
 /// default value for LM_HOME_PREFIX
-pub const LM_HOME_PREFIX_DEFAULT: &'static str = ".lm";
+pub const LM_HOME_PREFIX_DEFAULT: &str = ".lm";
 
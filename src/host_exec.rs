@@ -0,0 +1,184 @@
+//! Protocol between the host-exec client inside the container and the host daemon, every message
+//! is a frame with type, payload length and payload
+
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read, Write};
+
+/// Directory inside the container where the host daemon socket directory is mounted, it is not
+/// inside /run/host as the mountpoint would have to be created in root of the host
+pub const CONTAINER_SOCKET_DIR: &str = "/run/legumemanager";
+
+/// Name of the socket inside the socket directory
+pub const SOCKET_NAME: &str = "host-exec.sock";
+
+/// Path inside the container where the session bus of the host is mounted, it is used to execute
+/// commands using flatpak-session-helper without the host daemon
+pub const CONTAINER_SESSION_BUS: &str = "/run/legumemanager-session-bus";
+
+/// Variables that describe the container or its user and would break commands on the host
+const IGNORED_ENV: [&str; 12] = [
+    "PATH",
+    "HOME",
+    "SHELL",
+    "HOSTNAME",
+    "PWD",
+    "OLDPWD",
+    "SHLVL",
+    "_",
+    "container",
+    "CONTAINER_ID",
+    "TERMINFO_DIRS",
+    "LD_LIBRARY_PATH",
+];
+
+/// Checks if the variable should not be passed to commands executed on the host
+pub fn is_ignored_env(key: &str) -> bool {
+    IGNORED_ENV.contains(&key)
+        || key.starts_with("manager_")
+        || (key.starts_with("XDG_") && (key.ends_with("_HOME") || key.ends_with("_DIRS")))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    /// JSON encoded `HostExecRequest`, always the first frame sent by the client
+    Request,
+
+    /// Input of the command, empty payload closes it
+    Stdin,
+
+    Stdout,
+    Stderr,
+
+    /// New terminal size as rows and columns
+    Resize,
+
+    /// Exit code of the command, always the last frame sent by the daemon
+    Exit,
+}
+
+impl FrameKind {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Request => 0,
+            Self::Stdin => 1,
+            Self::Stdout => 2,
+            Self::Stderr => 3,
+            Self::Resize => 4,
+            Self::Exit => 5,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Request),
+            1 => Some(Self::Stdin),
+            2 => Some(Self::Stdout),
+            3 => Some(Self::Stderr),
+            4 => Some(Self::Resize),
+            5 => Some(Self::Exit),
+            _ => None,
+        }
+    }
+}
+
+/// Biggest payload of a request, argv and env are limited to few megabytes by the kernel anyway
+const MAX_REQUEST_SIZE: usize = 8 << 20;
+
+/// Biggest payload of other frames, stdio is sent in much smaller chunks
+const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// Command to execute on the host
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HostExecRequest {
+    pub argv: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub cwd: String,
+
+    /// Terminal size as rows and columns, a pseudo terminal is allocated if set
+    pub tty: Option<(u16, u16)>,
+}
+
+/// Writes whole frame at once so frames from multiple threads do not interleave
+pub fn write_frame(writer: &mut impl Write, kind: FrameKind, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.push(kind.to_u8());
+    frame.extend((payload.len() as u32).to_be_bytes());
+    frame.extend(payload);
+
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// Reads a frame, None when the connection was closed
+///
+/// The size is checked before anything is allocated as the other side may not be trusted
+pub fn read_frame(reader: &mut impl Read) -> std::io::Result<Option<(FrameKind, Vec<u8>)>> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {},
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let kind = FrameKind::from_u8(header[0])
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, format!("invalid frame type {}", header[0])))?;
+
+    let size = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let max_size = match kind {
+        FrameKind::Request => MAX_REQUEST_SIZE,
+        _ => MAX_FRAME_SIZE,
+    };
+
+    if size > max_size {
+        return Err(std::io::Error::new(ErrorKind::InvalidData, format!("frame of {} bytes is over the limit of {} bytes", size, max_size)));
+    }
+
+    let mut payload = vec![0u8; size];
+    reader.read_exact(&mut payload)?;
+
+    Ok(Some((kind, payload)))
+}
+
+pub fn encode_size(rows: u16, columns: u16) -> Vec<u8> {
+    [rows.to_be_bytes(), columns.to_be_bytes()].concat()
+}
+
+pub fn decode_size(payload: &[u8]) -> Option<(u16, u16)> {
+    match payload {
+        [a, b, c, d] => Some((u16::from_be_bytes([*a, *b]), u16::from_be_bytes([*c, *d]))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_round_trip() {
+        let mut buffer = vec![];
+        write_frame(&mut buffer, FrameKind::Stdout, b"hello").unwrap();
+        write_frame(&mut buffer, FrameKind::Resize, &encode_size(24, 80)).unwrap();
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), Some((FrameKind::Stdout, b"hello".to_vec())));
+
+        let (kind, payload) = read_frame(&mut reader).unwrap().unwrap();
+        assert_eq!(kind, FrameKind::Resize);
+        assert_eq!(decode_size(&payload), Some((24, 80)));
+
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn oversized_frame_is_refused() {
+        // only the header is sent, nothing should be allocated for the payload
+        for (kind, size) in [(FrameKind::Stdin, MAX_FRAME_SIZE + 1), (FrameKind::Request, MAX_REQUEST_SIZE + 1), (FrameKind::Stdin, u32::MAX as usize)] {
+            let mut header = vec![kind.to_u8()];
+            header.extend((size as u32).to_be_bytes());
+
+            let err = read_frame(&mut header.as_slice()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Structured records of init steps, init writes them as JSON lines into a log file and its output
+//! so they can be told apart from other output in the container logs
+
+use serde::{Deserialize, Serialize};
+
+/// File inside the container with records of the last init run
+pub const INIT_LOG_FILE: &str = "/run/lm/init.log";
+
+/// Prefix of every record line
+const RECORD_PREFIX: &str = "lm-init ";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InitEvent {
+    Start,
+    Done,
+    Failed,
+    Warning,
+    Info,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InitRecord {
+    /// Timestamp in RFC 3339 format
+    pub time: String,
+
+    pub step: String,
+    pub event: InitEvent,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u128>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl InitRecord {
+    /// Formats the record as single line
+    pub fn to_line(&self) -> String {
+        format!("{}{}", RECORD_PREFIX, serde_json::to_string(self).unwrap_or_default())
+    }
+
+    /// Parses record from a line, None if the line is not a record
+    pub fn from_line(line: &str) -> Option<Self> {
+        serde_json::from_str(line.trim_end().strip_prefix(RECORD_PREFIX)?).ok()
+    }
+
+    /// Formats the record in human readable form
+    pub fn pretty(&self) -> String {
+        let time = self.time.replacen('T', " ", 1);
+        let time = time.trim_end_matches('Z');
+        let duration = self.duration_ms.map(|x| format!("{}ms", x)).unwrap_or_default();
+        let message = self.message.as_deref().unwrap_or_default();
+
+        let text = match self.event {
+            InitEvent::Start => "started".to_string(),
+            InitEvent::Done => format!("done in {}", duration),
+            InitEvent::Failed => format!("failed after {}: {}", duration, message),
+            InitEvent::Warning => format!("warning: {}", message),
+            InitEvent::Info => message.to_string(),
+        };
+
+        format!("{} [{}] {}", time, self.step, text)
+    }
+}
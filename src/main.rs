@@ -1,18 +1,42 @@
 mod util;
 mod manager;
+mod backend;
 mod cli_host;
 mod cli_container;
 mod env_vars;
+mod mount_spec;
+mod init_log;
+mod host_exec;
+mod command_fallback;
 
 pub use anyhow::{Error, Result, Context};
 
 use std::path::Path;
 
-pub const VERSION: &'static str = concat!(env!("CARGO_PKG_VERSION_MAJOR"), env!("CARGO_PKG_VERSION_MINOR"), env!("CARGO_PKG_VERSION_PATCH"));
-pub const VERSION_STR: &'static str = env!("CARGO_PKG_VERSION");
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION_MAJOR"), env!("CARGO_PKG_VERSION_MINOR"), env!("CARGO_PKG_VERSION_PATCH"));
+pub const VERSION_STR: &str = env!("CARGO_PKG_VERSION");
+
+/// Version of labels and environment layout of containers, increment on breaking changes
+pub const CONTAINER_SCHEMA: u32 = 1;
+
+/// File created by init inside the container once the setup is finished, it is on tmpfs so it does
+/// not survive restarts
+pub const INIT_MARKER: &str = "/run/lm/initialized";
+
+/// File that exists inside the container while init installs essential packages, the host waits
+/// for init longer as installing can take minutes
+pub const BOOTSTRAP_MARKER: &str = "/run/lm/bootstrapping";
+
+/// File in the directory kept on host for each container with hostname set using set-hostname,
+/// the directory is mounted at `host_exec::CONTAINER_SOCKET_DIR` so init applies it on start
+///
+/// Neither labels nor hostname of the container can be changed after it is created so the new
+/// name is kept next to the container metadata on host, without it the container hostname is used
+pub const HOSTNAME_FILE: &str = "hostname";
 
 fn main() -> Result<()> {
-    let force_host = if cfg!(debug_assertions) {
+    #[cfg(debug_assertions)]
+    let force_host = {
         let value = std::env::var(env_vars::LM_FORCE_HOST).is_ok();
 
         if value {
@@ -20,10 +44,11 @@ fn main() -> Result<()> {
         }
 
         value
-    } else {
-        false
     };
 
+    #[cfg(not(debug_assertions))]
+    let force_host = false;
+
     if (Path::new("/run/.containerenv").exists()
         || Path::new("/.dockerenv").exists()
         || std::env::var("container").is_ok())
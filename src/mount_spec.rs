@@ -0,0 +1,146 @@
+//! Table of host paths init mounts inside the container, it is written into the container at
+//! creation and read by init on every start
+
+use crate::{util, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// File inside the container containing the mount spec, without it the default profile is used
+pub const MOUNT_SPEC_FILE: &str = "/etc/legumemanager-mounts.json";
+
+/// Path inside the container where root of the host is mounted
+pub const HOST_ROOT: &str = "/run/host";
+
+/// Mounts read-only
+const DEFAULT_RO_MOUNTS: [&str; 3] = [
+    "/etc/localtime",
+    "/var/lib/systemd/coredump",
+    "/var/log/journal",
+];
+
+/// Mounts read-write
+const DEFAULT_RW_MOUNTS: [&str; 15] = [
+    "/etc/host.conf",
+    "/etc/machine-id",
+    "/media",
+    "/mnt",
+    "/run/libvirt",
+    "/run/media",
+    "/run/netconfig/",
+    "/run/systemd/journal",
+    "/run/systemd/resolve/",
+    "/run/systemd/seats",
+    "/run/systemd/sessions",
+    "/run/systemd/users",
+    "/run/udev",
+    "/var/lib/libvirt",
+    "/var/mnt",
+];
+
+/// Base set of mounts that is extended by the spec
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MountProfile {
+    /// Host paths that are useful in most containers
+    #[default]
+    Default,
+
+    /// Nothing is mounted except what is added
+    None,
+}
+
+/// Host path mounted into the container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HostMount {
+    /// Path inside the container
+    pub destination: String,
+
+    /// Path of the source, defaults to the destination inside `HOST_ROOT`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl HostMount {
+    pub fn new(destination: &str, read_only: bool) -> Self {
+        Self {
+            destination: destination.into(),
+            source: None,
+            read_only,
+        }
+    }
+
+    /// Parses mount in 'PATH' or 'PATH:ro' format
+    pub fn parse(value: &str) -> Result<Self> {
+        let (path, read_only) = match value.rsplit_once(':') {
+            Some((path, "ro")) => (path, true),
+            Some((path, "rw")) => (path, false),
+            Some(_) => return Err(Error::msg(format!("invalid mount '{}', expected 'PATH' or 'PATH:ro'", value))),
+            None => (value, false),
+        };
+
+        if !path.starts_with('/') {
+            return Err(Error::msg(format!("mount path '{}' has to be absolute", path)));
+        }
+
+        Ok(Self::new(path, read_only))
+    }
+
+    pub fn source(&self) -> String {
+        self.source.clone()
+            .unwrap_or_else(|| format!("{}{}", HOST_ROOT, self.destination))
+    }
+}
+
+/// Mounts of a container as a profile with additions and removals
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MountSpec {
+    #[serde(default)]
+    pub profile: MountProfile,
+
+    /// Mounts added on top of the profile, they replace profile mounts with the same destination
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add: Vec<HostMount>,
+
+    /// Destinations removed from the profile
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+}
+
+/// Paths are compared without trailing slash
+fn same_path(a: &str, b: &str) -> bool {
+    Path::new(a) == Path::new(b)
+}
+
+impl MountSpec {
+    /// Loads spec from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        util::load_json(path, "mount spec")
+    }
+
+    /// Returns true if the spec is the same as the default profile
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the final list of mounts in order they should be mounted
+    pub fn mounts(&self) -> Vec<HostMount> {
+        let mut mounts: Vec<HostMount> = match self.profile {
+            MountProfile::Default => DEFAULT_RO_MOUNTS.iter().map(|x| HostMount::new(x, true))
+                .chain(DEFAULT_RW_MOUNTS.iter().map(|x| HostMount::new(x, false)))
+                .collect(),
+            MountProfile::None => vec![],
+        };
+
+        mounts.retain(|x| {
+            !self.remove.iter().any(|y| same_path(&x.destination, y))
+                && !self.add.iter().any(|y| same_path(&x.destination, &y.destination))
+        });
+
+        mounts.extend(self.add.iter().cloned());
+
+        mounts
+    }
+}
@@ -1,4 +1,8 @@
+use crate::{Context, Result};
+use serde::de::DeserializeOwned;
+use std::path::Path;
 use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Check whether executable exists in PATH
 #[cfg(target_os = "linux")]
@@ -11,3 +15,85 @@ pub fn executable_exists(cmd: &str) -> bool {
     output.status.success()
 }
 
+/// Reads and parses JSON file, what is the name of the contents used in errors
+pub fn load_json<T: DeserializeOwned>(path: &Path, what: &str) -> Result<T> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("failed to read {} {:?}", what, path))?;
+
+    serde_json::from_slice(&data)
+        .with_context(|| format!("failed to parse {} {:?}", what, path))
+}
+
+/// Returns size of the terminal as (rows, columns), None if fd is not a terminal
+pub fn get_window_size(fd: i32) -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+
+    // SAFETY: ioctl only writes into the winsize struct
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+
+    Some((size.ws_row, size.ws_col))
+}
+
+/// Puts the terminal into raw mode, original mode is restored when dropped
+pub struct RawTerminal {
+    fd: i32,
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    /// Enables raw mode, None if fd is not a terminal
+    pub fn new(fd: i32) -> Option<Self> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+
+        // SAFETY: tcgetattr only writes into the termios struct
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return None;
+        }
+
+        let mut raw = original;
+
+        // SAFETY: both functions only operate on the termios struct and the fd
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+        }
+
+        Some(Self { fd, original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        // SAFETY: restoring the previously read termios
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Formats time as RFC 3339 timestamp in UTC with milliseconds (eg. '2024-01-31T12:00:00.000Z')
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (hours, minutes, seconds) = ((secs / 3600) % 24, (secs / 60) % 60, secs % 60);
+
+    // converts days since epoch to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, hours, minutes, seconds, since_epoch.subsec_millis(),
+    )
+}